@@ -0,0 +1,143 @@
+use crate::commands::anthropic::ApiCredentials;
+use anyhow::{bail, Context, Result};
+use reqwest::header::{HeaderName, HeaderValue};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+const BASE_URL_VAR: &str = "ANTHROPIC_BASE_URL";
+const VERSION_VAR: &str = "ANTHROPIC_VERSION";
+const BETA_VAR: &str = "ANTHROPIC_BETA";
+const HEADERS_VAR: &str = "ANTHROPIC_HEADERS";
+const PROXY_VAR: &str = "CLAUDE_LITE_PROXY";
+const CA_CERTS_VAR: &str = "CLAUDE_LITE_CA_CERTS";
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const DEFAULT_VERSION: &str = "2023-06-01";
+
+/// Where requests to the Anthropic API go and what they carry, for every key
+/// and credential profile. Profiles can still set their own base URL and headers.
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    pub base_url: String,
+    /// Sent as `anthropic-version`
+    pub version: String,
+    /// Sent as `anthropic-beta`
+    pub beta: Vec<String>,
+    /// Sent with every request, names in lower case
+    pub headers: BTreeMap<String, String>,
+    /// Shared by all requests, with the proxy and CA certificates applied
+    pub client: reqwest::Client,
+}
+
+impl ApiConfig {
+    /// Defaults to the public API with no extra headers. `ANTHROPIC_HEADERS`
+    /// is a JSON object of header names to values, `ANTHROPIC_BETA` a comma
+    /// separated list of flags and `CLAUDE_LITE_CA_CERTS` a list of PEM files,
+    /// separated like `PATH`. Without `CLAUDE_LITE_PROXY`, the usual
+    /// `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` variables apply.
+    pub fn from_env() -> Result<Self> {
+        let base_url = match var(BASE_URL_VAR) {
+            Some(base_url) => check_base_url(&base_url).with_context(|| format!("Invalid {}", BASE_URL_VAR))?,
+            None => DEFAULT_BASE_URL.to_string(),
+        };
+        let version = var(VERSION_VAR).unwrap_or_else(|| DEFAULT_VERSION.to_string());
+        HeaderValue::try_from(version.as_str()).with_context(|| format!("Invalid {}", VERSION_VAR))?;
+
+        let beta: Vec<String> = var(BETA_VAR)
+            .map(|flags| split_list(&flags))
+            .unwrap_or_default();
+        HeaderValue::try_from(beta.join(",")).with_context(|| format!("Invalid {}", BETA_VAR))?;
+
+        let headers = match var(HEADERS_VAR) {
+            Some(json) => {
+                let headers: BTreeMap<String, String> = serde_json::from_str(&json)
+                    .with_context(|| format!("{} must be a JSON object of header names to values", HEADERS_VAR))?;
+                check_headers(headers).with_context(|| format!("Invalid {}", HEADERS_VAR))?
+            }
+            None => BTreeMap::new(),
+        };
+
+        let mut builder = reqwest::Client::builder();
+        if let Some(proxy) = var(PROXY_VAR) {
+            let proxy = reqwest::Proxy::all(&proxy).with_context(|| format!("Invalid {}", PROXY_VAR))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(paths) = env::var_os(CA_CERTS_VAR) {
+            for path in env::split_paths(&paths).filter(|path| !path.as_os_str().is_empty()) {
+                let pem = fs::read(&path).with_context(|| format!("Failed to read CA certificates from {:?}", path))?;
+                let certs = reqwest::Certificate::from_pem_bundle(&pem)
+                    .with_context(|| format!("{:?} is not a PEM certificate file", path))?;
+                if certs.is_empty() {
+                    bail!("{:?} holds no certificates", path);
+                }
+                for cert in certs {
+                    builder = builder.add_root_certificate(cert);
+                }
+            }
+        }
+        let client = builder.build().context("Failed to set up the HTTP client")?;
+
+        Ok(Self {
+            base_url,
+            version,
+            beta,
+            headers,
+            client,
+        })
+    }
+
+    /// Credentials for the key with this configuration's endpoint and headers.
+    pub fn credentials(&self, api_key: String) -> ApiCredentials {
+        let mut headers = self.headers.clone();
+        if !self.beta.is_empty() {
+            headers.insert("anthropic-beta".to_string(), self.beta.join(","));
+        }
+
+        ApiCredentials {
+            client: self.client.clone(),
+            api_key,
+            base_url: self.base_url.clone(),
+            version: self.version.clone(),
+            headers,
+        }
+    }
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// Comma separated values, trimmed, without empty ones.
+pub fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// An http(s) URL, without a trailing slash.
+pub fn check_base_url(base_url: &str) -> Result<String> {
+    match reqwest::Url::parse(base_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(base_url.trim_end_matches('/').to_string()),
+        _ => bail!("'{}' is not an http(s) URL", base_url),
+    }
+}
+
+/// Valid header names and values, names in lower case. The key and API
+/// version are set by the app and can't be overridden here.
+pub fn check_headers(headers: BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+    let mut checked = BTreeMap::new();
+    for (name, value) in headers {
+        let name = name.trim().to_ascii_lowercase();
+        if HeaderName::try_from(name.as_str()).is_err() || HeaderValue::try_from(value.as_str()).is_err() {
+            bail!("'{}' is not a valid header", name);
+        }
+        if matches!(name.as_str(), "x-api-key" | "anthropic-version") {
+            bail!("The '{}' header is set by the app", name);
+        }
+        checked.insert(name, value);
+    }
+    Ok(checked)
+}
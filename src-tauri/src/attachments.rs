@@ -0,0 +1,239 @@
+use crate::db::models::MessageAttachment;
+use crate::markdown::fenced;
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::Encoding;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+/// Largest image the API accepts
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+/// Longest edge the models look at; the API scales anything bigger down anyway
+const MAX_IMAGE_EDGE: u32 = 1568;
+/// Larger files aren't decoded at all
+const MAX_SOURCE_IMAGE_BYTES: u64 = 100 * 1024 * 1024;
+/// A whole request may be at most 32 MB
+const MAX_DOCUMENT_BYTES: u64 = 32 * 1024 * 1024;
+/// Text goes into the context whole, so files much bigger than this would crowd out the chat
+const MAX_TEXT_BYTES: usize = 1024 * 1024;
+const JPEG_QUALITY: u8 = 85;
+
+/// How text attachments are stored: converted to UTF-8, whatever they were saved as
+pub const TEXT_MEDIA_TYPE: &str = "text/plain";
+
+/// Read an image, PDF or text file, telling them apart by content rather than name.
+pub fn read_file(path: &Path) -> Result<MessageAttachment> {
+    let name = file_name(path);
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .len();
+
+    let mut head = [0u8; 16];
+    let read = File::open(path)?.read(&mut head)?;
+    let head = &head[..read];
+
+    if image_media_type(head).is_some() {
+        if size > MAX_SOURCE_IMAGE_BYTES {
+            bail!("{} is too large to attach", name);
+        }
+        return prepare_image(std::fs::read(path)?, Some(name));
+    }
+
+    let limit = if head.starts_with(b"%PDF-") {
+        MAX_DOCUMENT_BYTES
+    } else {
+        MAX_TEXT_BYTES as u64
+    };
+    if size > limit {
+        bail!("{} is larger than the {} MB allowed", name, limit / (1024 * 1024));
+    }
+    prepare_document(std::fs::read(path)?, name)
+}
+
+/// Check an image against the API's limits, scaling it down to the largest
+/// size the model uses if it is bigger. Images that already fit are kept
+/// byte for byte.
+pub fn prepare_image(bytes: Vec<u8>, name: Option<String>) -> Result<MessageAttachment> {
+    let label = name.clone().unwrap_or_else(|| "Image".to_string());
+    let media_type = image_media_type(&bytes).with_context(|| format!("{} is not a JPEG, PNG, GIF or WebP image", label))?;
+
+    let reader = ImageReader::new(Cursor::new(&bytes)).with_guessed_format()?;
+    let (width, height) = reader
+        .into_dimensions()
+        .with_context(|| format!("Failed to read {}", label))?;
+    if width.max(height) <= MAX_IMAGE_EDGE && bytes.len() <= MAX_IMAGE_BYTES {
+        return Ok(MessageAttachment::from_bytes("image", media_type.to_string(), name, &bytes));
+    }
+
+    let image = image::load_from_memory(&bytes).with_context(|| format!("Failed to decode {}", label))?;
+    let image = if width.max(height) > MAX_IMAGE_EDGE {
+        image.resize(MAX_IMAGE_EDGE, MAX_IMAGE_EDGE, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    // Screenshots and anything with transparency stay PNG while that fits
+    if matches!(media_type, "image/png" | "image/gif") {
+        let png = encode(&image, ImageFormat::Png)?;
+        if png.len() <= MAX_IMAGE_BYTES {
+            return Ok(MessageAttachment::from_bytes("image", "image/png".to_string(), name, &png));
+        }
+    }
+
+    let jpeg = encode(&image, ImageFormat::Jpeg)?;
+    if jpeg.len() > MAX_IMAGE_BYTES {
+        bail!("{} is still larger than 5 MB after scaling it down", label);
+    }
+    Ok(MessageAttachment::from_bytes("image", "image/jpeg".to_string(), name, &jpeg))
+}
+
+/// Check a document against the API's limits. PDFs are kept as they are;
+/// anything else must read as text, and is stored as UTF-8.
+pub fn prepare_document(bytes: Vec<u8>, name: String) -> Result<MessageAttachment> {
+    if bytes.starts_with(b"%PDF-") {
+        if bytes.len() as u64 > MAX_DOCUMENT_BYTES {
+            bail!("{} is larger than the 32 MB the API accepts", name);
+        }
+        return Ok(MessageAttachment::from_bytes("document", "application/pdf".to_string(), Some(name), &bytes));
+    }
+
+    if bytes.len() > MAX_TEXT_BYTES {
+        bail!("{} is larger than the 1 MB allowed for text files", name);
+    }
+    let text = decode_text(&bytes)
+        .with_context(|| format!("{} is not a supported file type (images, PDFs and text files)", name))?;
+    Ok(MessageAttachment::from_bytes(
+        "document",
+        TEXT_MEDIA_TYPE.to_string(),
+        Some(name),
+        text.as_bytes(),
+    ))
+}
+
+/// Decode text saved in any common encoding: a byte order mark decides if
+/// there is one, then UTF-8, then chardetng's best guess. NUL bytes outside
+/// UTF-16 mean the file is binary.
+fn decode_text(bytes: &[u8]) -> Option<String> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, had_errors) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return (!had_errors).then(|| text.into_owned());
+    }
+    if bytes.contains(&0) {
+        return None;
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Some(text.to_string());
+    }
+
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, Utf8Detection::Deny);
+    let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+    (!had_errors).then(|| text.into_owned())
+}
+
+/// A text attachment as the model sees it: the file name, then the content
+/// fenced and tagged with its language.
+pub fn text_for_model(attachment: &MessageAttachment) -> Option<String> {
+    let bytes = STANDARD.decode(attachment.data.as_deref()?).ok()?;
+    let text = String::from_utf8(bytes).ok()?;
+    let name = attachment.name.as_deref().unwrap_or("document");
+    Some(format!("{}\n{}", name, fenced(text.trim_end(), language(name))))
+}
+
+/// Fence language for a file, from its extension or, for the likes of
+/// `Dockerfile`, its whole name.
+fn language(name: &str) -> &'static str {
+    let name = name.to_ascii_lowercase();
+    let extension = name.rsplit_once('.').map(|(_, extension)| extension).unwrap_or(&name);
+    match extension {
+        "rs" => "rust",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "py" | "pyi" => "python",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "m" | "mm" => "objectivec",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "r" => "r",
+        "dart" => "dart",
+        "ex" | "exs" => "elixir",
+        "erl" => "erlang",
+        "hs" => "haskell",
+        "ml" | "mli" => "ocaml",
+        "clj" | "cljs" => "clojure",
+        "zig" => "zig",
+        "nix" => "nix",
+        "sh" | "bash" | "zsh" => "bash",
+        "fish" => "fish",
+        "ps1" => "powershell",
+        "bat" | "cmd" => "batch",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "less" => "less",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "xml" | "svg" => "xml",
+        "json" | "jsonl" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "ini" | "cfg" | "conf" => "ini",
+        "md" | "markdown" => "markdown",
+        "csv" => "csv",
+        "tsv" => "tsv",
+        "graphql" | "gql" => "graphql",
+        "proto" => "protobuf",
+        "tf" => "hcl",
+        "diff" | "patch" => "diff",
+        "dockerfile" => "dockerfile",
+        "makefile" => "makefile",
+        _ => "",
+    }
+}
+
+/// The media type of an image format the API accepts, judged by content.
+fn image_media_type(bytes: &[u8]) -> Option<&'static str> {
+    match image::guess_format(bytes).ok()? {
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Gif => Some("image/gif"),
+        ImageFormat::WebP => Some("image/webp"),
+        _ => None,
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    match format {
+        // JPEG has no alpha channel
+        ImageFormat::Jpeg => {
+            JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY).encode_image(&DynamicImage::ImageRgb8(image.to_rgb8()))?
+        }
+        _ => image.write_to(&mut out, format)?,
+    }
+    Ok(out.into_inner())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
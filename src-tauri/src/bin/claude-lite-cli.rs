@@ -0,0 +1,3 @@
+fn main() {
+    claude_lite_lib::cli::run()
+}
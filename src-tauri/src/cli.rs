@@ -0,0 +1,439 @@
+use crate::api_config::ApiConfig;
+use crate::commands::anthropic::{self, EventSink, GenerationEvent};
+use crate::commands::export::{self, ExportFormat};
+use crate::commands::{api_credentials, post_message, NewMessage};
+use crate::db;
+use crate::import;
+use crate::db::models::{Chat, Project, ProjectSettings};
+use crate::secrets::{self, SecretStore};
+use crate::state::AppState;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use serde_json::json;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Use Claude Lite chats from a terminal. Reads and writes the desktop app's database.
+#[derive(Parser)]
+#[command(name = "claude-lite-cli", version)]
+struct Cli {
+    /// Database file to use instead of the desktop app's
+    #[arg(long, env = "CLAUDE_LITE_DB", global = true)]
+    db: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Projects in the database
+    Projects {
+        #[command(subcommand)]
+        command: ProjectsCommand,
+    },
+    /// Chats in a project or outside any
+    Chats {
+        #[command(subcommand)]
+        command: ChatsCommand,
+    },
+    /// A single chat
+    Chat {
+        #[command(subcommand)]
+        command: ChatCommand,
+    },
+    /// Send a prompt and stream the reply to stdout. Piped stdin is appended to the prompt.
+    Ask {
+        /// Project name or id; its settings and tools apply
+        #[arg(short, long)]
+        project: Option<String>,
+        /// Continue this chat instead of starting a new one
+        #[arg(short, long)]
+        chat: Option<String>,
+        #[arg(short, long)]
+        model: Option<String>,
+        /// Enable extended thinking and print it to stderr
+        #[arg(long)]
+        thinking: bool,
+        /// Image or PDF to attach; may be repeated
+        #[arg(short, long = "attach", value_name = "FILE")]
+        attach: Vec<PathBuf>,
+        prompt: Vec<String>,
+    },
+    /// Import a claude.ai or ChatGPT data export: the zip or its conversations.json
+    Import {
+        path: PathBuf,
+        /// Project name or id; defaults to one named after the source
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+    /// Export a chat, or all chats of a project
+    Export {
+        #[arg(required_unless_present = "project")]
+        chat_id: Option<String>,
+        /// Project name or id to export instead of a single chat
+        #[arg(short, long, conflicts_with = "chat_id")]
+        project: Option<String>,
+        #[arg(short, long, value_enum, default_value = "markdown")]
+        format: ExportFormat,
+        /// File to write instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProjectsCommand {
+    /// List projects as `id<TAB>name`
+    List,
+}
+
+#[derive(Subcommand)]
+enum ChatsCommand {
+    /// List chats as `id<TAB>updated<TAB>title`, most recent first
+    List {
+        /// Project name or id; chats outside any project when omitted
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ChatCommand {
+    /// Print the messages on a chat's active branch
+    Show {
+        chat_id: String,
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Entry point of the `claude-lite-cli` binary.
+pub fn run() {
+    dotenvy::dotenv().ok();
+    let cli = Cli::parse();
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime");
+    match runtime.block_on(execute(cli)) {
+        Ok(()) => {}
+        Err(e) if e.is::<Interrupted>() => std::process::exit(130),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Ctrl-C ended the command. It still returns normally, so MCP servers are shut down.
+#[derive(Debug)]
+struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// The desktop app keeps its database in the platform data directory, under the bundle identifier.
+fn default_db_path() -> Result<PathBuf> {
+    let config: serde_json::Value = serde_json::from_str(include_str!("../tauri.conf.json"))?;
+    let identifier = config["identifier"]
+        .as_str()
+        .context("tauri.conf.json has no identifier")?;
+    let data_dir = dirs::data_dir().context("Could not determine the data directory")?;
+    Ok(data_dir.join(identifier).join("claude.db"))
+}
+
+async fn execute(cli: Cli) -> Result<()> {
+    let db_path = match cli.db {
+        Some(path) => path,
+        None => default_db_path()?,
+    };
+    let pool = db::init_db(db_path.clone())
+        .await
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    // The desktop app's saved key, which lives next to its database
+    let store = SecretStore::new(db_path.parent().map(Path::to_path_buf).unwrap_or_default());
+    let loader = store.clone();
+    let api_key = tokio::task::spawn_blocking(move || secrets::load_api_key(&loader)).await?;
+    let state = AppState::new(pool, Ok(ApiConfig::from_env()?), store, api_key);
+
+    let result = match cli.command {
+        Command::Projects {
+            command: ProjectsCommand::List,
+        } => list_projects(&state).await,
+        Command::Chats {
+            command: ChatsCommand::List { project },
+        } => list_chats(&state, project).await,
+        Command::Chat {
+            command: ChatCommand::Show { chat_id, json },
+        } => show_chat(&state, &chat_id, json).await,
+        Command::Ask {
+            project,
+            chat,
+            model,
+            thinking,
+            attach,
+            prompt,
+        } => ask(&state, project, chat, model, thinking, attach, prompt).await,
+        Command::Import { path, project } => import(&state, path, project).await,
+        Command::Export {
+            chat_id,
+            project,
+            format,
+            output,
+        } => export(&state, chat_id, project, format, output).await,
+    };
+
+    state.mcp.shutdown();
+    result
+}
+
+/// Find a project by id, or by name ignoring case.
+async fn find_project(state: &AppState, id_or_name: &str) -> Result<Project> {
+    let projects = db::list_projects(&state.db).await?;
+    if let Some(project) = projects.iter().find(|p| p.id == id_or_name) {
+        return Ok(project.clone());
+    }
+
+    let mut matches = projects
+        .into_iter()
+        .filter(|p| p.name.eq_ignore_ascii_case(id_or_name));
+    match (matches.next(), matches.next()) {
+        (Some(project), None) => Ok(project),
+        (Some(_), Some(_)) => bail!("Several projects are named '{}'; use its id", id_or_name),
+        (None, _) => bail!("No project named '{}'", id_or_name),
+    }
+}
+
+async fn get_chat(state: &AppState, chat_id: &str) -> Result<Chat> {
+    db::get_chat(&state.db, chat_id)
+        .await
+        .with_context(|| format!("Failed to load chat {}", chat_id))
+}
+
+async fn list_projects(state: &AppState) -> Result<()> {
+    for project in db::list_projects(&state.db).await? {
+        println!("{}\t{}", project.id, project.name);
+    }
+    Ok(())
+}
+
+async fn list_chats(state: &AppState, project: Option<String>) -> Result<()> {
+    let project_id = match project {
+        Some(project) => Some(find_project(state, &project).await?.id),
+        None => None,
+    };
+
+    for chat in db::list_chats(&state.db, project_id).await? {
+        println!("{}\t{}\t{}", chat.id, chat.updated_at, chat.title);
+    }
+    Ok(())
+}
+
+async fn show_chat(state: &AppState, chat_id: &str, as_json: bool) -> Result<()> {
+    let chat = get_chat(state, chat_id).await?;
+    let messages = db::list_messages(&state.db, chat_id).await?;
+
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&json!({ "chat": chat, "messages": messages }))?);
+        return Ok(());
+    }
+
+    println!("# {}\n", chat.title);
+    for message in &messages {
+        match (&message.role[..], &message.model) {
+            ("assistant", Some(model)) => println!("## assistant ({})  {}\n", model, message.created_at),
+            (role, _) => println!("## {}  {}\n", role, message.created_at),
+        }
+        println!("{}\n", message.content);
+    }
+    Ok(())
+}
+
+async fn import(state: &AppState, path: PathBuf, project: Option<String>) -> Result<()> {
+    let project_id = match project {
+        Some(project) => Some(find_project(state, &project).await?.id),
+        None => None,
+    };
+
+    let summary = import::import_file(&state.db, path, project_id).await?;
+    let project = db::get_project(&state.db, &summary.project_id).await?;
+    println!(
+        "Imported {} messages into '{}': {} new chats, {} updated",
+        summary.messages_imported, project.name, summary.chats_created, summary.chats_updated
+    );
+    Ok(())
+}
+
+async fn export(
+    state: &AppState,
+    chat_id: Option<String>,
+    project: Option<String>,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let document = match (chat_id, project) {
+        (_, Some(project)) => {
+            let project = find_project(state, &project).await?;
+            export::render_project(&state.db, &project.id, format).await?
+        }
+        (Some(chat_id), None) => {
+            get_chat(state, &chat_id).await?;
+            export::render_chat(&state.db, &chat_id, format).await?
+        }
+        (None, None) => bail!("Give a chat id or --project"),
+    };
+
+    match output {
+        Some(path) => std::fs::write(&path, document).with_context(|| format!("Failed to write {:?}", path))?,
+        None => println!("{}", document.trim_end()),
+    }
+    Ok(())
+}
+
+/// Prints the reply as it streams. Everything but the reply text goes to stderr,
+/// so stdout can be piped.
+struct TerminalSink {
+    thinking: bool,
+}
+
+impl EventSink for TerminalSink {
+    fn emit(&self, event: GenerationEvent) {
+        match event {
+            GenerationEvent::TextDelta { delta } => {
+                let mut stdout = io::stdout();
+                let _ = stdout.write_all(delta.as_bytes());
+                let _ = stdout.flush();
+            }
+            GenerationEvent::ThinkingDelta { delta, .. } if self.thinking => eprint!("{}", delta),
+            GenerationEvent::Retry {
+                attempt,
+                max_retries,
+                delay_ms,
+                reason,
+            } => eprintln!(
+                "[retry {}/{} in {:.1}s: {}]",
+                attempt,
+                max_retries,
+                delay_ms as f64 / 1000.0,
+                reason
+            ),
+            GenerationEvent::Compacted {
+                omitted_message_ids,
+                summarized,
+            } => eprintln!(
+                "[{} earlier messages {} to fit the context window]",
+                omitted_message_ids.len(),
+                if summarized { "summarised" } else { "left out" }
+            ),
+            GenerationEvent::ToolUse { name, .. } => eprintln!("[tool: {}]", name),
+            GenerationEvent::ToolResult {
+                content,
+                is_error: true,
+                ..
+            } => eprintln!("[tool error: {}]", content),
+            _ => {}
+        }
+    }
+}
+
+async fn ask(
+    state: &AppState,
+    project: Option<String>,
+    chat_id: Option<String>,
+    model: Option<String>,
+    thinking: bool,
+    attach: Vec<PathBuf>,
+    prompt: Vec<String>,
+) -> Result<()> {
+    let mut content = prompt.join(" ");
+    if !io::stdin().is_terminal() {
+        let mut piped = String::new();
+        io::stdin().read_to_string(&mut piped)?;
+        if !piped.trim().is_empty() {
+            if !content.is_empty() {
+                content.push_str("\n\n");
+            }
+            content.push_str(piped.trim_end());
+        }
+    }
+    if content.trim().is_empty() && attach.is_empty() {
+        bail!("No prompt given");
+    }
+
+    let project_id = match project {
+        Some(project) => Some(find_project(state, &project).await?.id),
+        None => None,
+    };
+    let (chat, is_new) = match chat_id {
+        Some(chat_id) => (get_chat(state, &chat_id).await?, false),
+        None => (db::create_chat(&state.db, project_id.clone()).await?, true),
+    };
+
+    let message = NewMessage {
+        content: content.clone(),
+        project_id,
+        model,
+        images: None,
+        documents: None,
+        file_paths: Some(attach.iter().map(|path| path.to_string_lossy().into_owned()).collect()),
+        extended_thinking: thinking,
+    };
+
+    // Ctrl-C stops the reply but still saves what arrived; a second one quits.
+    // Handling it replaces the default SIGINT behaviour, so anything else quits too.
+    let sink = TerminalSink { thinking };
+    let mut send = Box::pin(post_message(state, &sink, &chat.id, message));
+    let reply = tokio::select! {
+        reply = &mut send => Some(reply),
+        _ = tokio::signal::ctrl_c() => {
+            if state.cancel_generation(&chat.id) {
+                tokio::select! {
+                    reply = &mut send => Some(reply),
+                    _ = tokio::signal::ctrl_c() => None,
+                }
+            } else {
+                // Nothing streaming yet, so nothing to save
+                None
+            }
+        }
+    };
+    // Ends the generation if it is still running
+    drop(send);
+    println!();
+
+    // A chat created here that got no reply isn't kept
+    if is_new && discard_if_unanswered(state, &chat.id).await? {
+        reply.ok_or(Interrupted)??;
+        return Ok(());
+    }
+    let reply = reply.ok_or(Interrupted)??;
+
+    // A reply cancelled before any text has nothing to title the chat by
+    if is_new && !reply.content.is_empty() {
+        let settings = match &chat.project_id {
+            Some(project_id) => serde_json::from_str(&db::get_project(&state.db, project_id).await?.settings_json)?,
+            None => ProjectSettings::default(),
+        };
+        if let Ok(credentials) = api_credentials(state, &settings).await {
+            if let Ok((title, usage)) = anthropic::generate_chat_title(&credentials, content, reply.content.clone()).await {
+                db::update_chat_title(&state.db, &chat.id, title).await?;
+                db::record_usage(&state.db, &usage.for_chat("title", &chat.id, chat.project_id.clone(), anthropic::TITLE_MODEL)).await?;
+            }
+        }
+    }
+    eprintln!("[chat: {}]", chat.id);
+    Ok(())
+}
+
+/// Delete the chat if it has no reply. Returns whether it was deleted.
+async fn discard_if_unanswered(state: &AppState, chat_id: &str) -> Result<bool> {
+    let messages = db::list_messages(&state.db, chat_id).await?;
+    if messages.iter().any(|m| m.role == "assistant") {
+        return Ok(false);
+    }
+    db::delete_chat(&state.db, chat_id).await?;
+    Ok(true)
+}
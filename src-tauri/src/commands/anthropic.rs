@@ -1,23 +1,111 @@
-use crate::db::models::{Message, ProjectSettings};
-use crate::error::{AppError, Result};
+use crate::attachments;
+use crate::db::models::{CacheTtl, Message, MessageAttachment, MessageUsage, ProjectSettings, PromptCache, RetryPolicy};
+use crate::error::{ApiError, AppError, Result};
+use crate::tools::{ToolDefinition, ToolRegistry};
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
-use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::ipc::Channel;
+use tokio::sync::Notify;
+use uuid::Uuid;
 
-const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1/messages";
-const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MESSAGES_PATH: &str = "/v1/messages";
+const COUNT_TOKENS_PATH: &str = "/v1/messages/count_tokens";
+const MODELS_PATH: &str = "/v1/models";
+
+/// Upper bound on model → tool → model round trips for a single reply
+const MAX_TOOL_ROUNDS: u32 = 10;
+/// Longest summary of older turns a compaction may write
+pub const SUMMARY_MAX_TOKENS: u32 = 4096;
+/// Model that titles chats
+pub const TITLE_MODEL: &str = "claude-3-5-sonnet-20241022";
+
+/// The key a request authenticates with, where it goes and the headers sent
+/// with it: the configured API with the default key, or with a project's
+/// credential profile applied.
+#[derive(Clone)]
+pub struct ApiCredentials {
+    pub client: reqwest::Client,
+    pub api_key: String,
+    pub base_url: String,
+    pub version: String,
+    /// Names in lower case
+    pub headers: BTreeMap<String, String>,
+}
+
+impl ApiCredentials {
+    /// Use a credential profile's endpoint and headers. Its beta flags are
+    /// added to the configured ones; other headers replace them.
+    pub fn with_profile(mut self, base_url: Option<String>, headers: &BTreeMap<String, String>) -> Self {
+        if let Some(base_url) = base_url {
+            self.base_url = base_url.trim_end_matches('/').to_string();
+        }
+        for (name, value) in headers {
+            let name = name.to_ascii_lowercase();
+            match self.headers.get_mut(&name) {
+                Some(flags) if name == "anthropic-beta" => {
+                    *flags = format!("{},{}", flags, value);
+                }
+                _ => {
+                    self.headers.insert(name, value.clone());
+                }
+            }
+        }
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// The configured and profile headers, then the ones every request needs, which win.
+    fn header_map(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            // Checked when the configuration is read or the profile saved
+            if let (Ok(name), Ok(value)) = (HeaderName::try_from(name.as_str()), HeaderValue::try_from(value.as_str())) {
+                headers.insert(name, value);
+            }
+        }
+        if let Ok(api_key) = HeaderValue::try_from(self.api_key.as_str()) {
+            headers.insert("x-api-key", api_key);
+        }
+        if let Ok(version) = HeaderValue::try_from(self.version.as_str()) {
+            headers.insert("anthropic-version", version);
+        }
+        headers
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(self.url(path))
+            .headers(self.header_map())
+            .header("content-type", "application/json")
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client.get(self.url(path)).headers(self.header_map())
+    }
+}
 
 #[derive(Debug, Serialize)]
 struct AnthropicRequest {
     model: String,
     max_tokens: u32,
     temperature: f32,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    system: Option<String>,
+    /// Text blocks rather than a plain string, so the prompt can carry a cache breakpoint
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    system: Vec<ContentBlock>,
     messages: Vec<AnthropicMessage>,
     stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     thinking: Option<ThinkingConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolDefinition>,
 }
 
 #[derive(Debug, Serialize)]
@@ -27,29 +115,96 @@ struct ThinkingConfig {
     budget_tokens: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Marks the end of a prompt prefix the API should cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    control_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<String>,
+}
+
+impl CacheControl {
+    fn new(cache: &PromptCache) -> Option<Self> {
+        cache.enabled.then(|| CacheControl {
+            control_type: "ephemeral".to_string(),
+            ttl: (cache.ttl == CacheTtl::OneHour).then(|| "1h".to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 enum MessageContent {
     Text(String),
     Blocks(Vec<ContentBlock>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 enum ContentBlock {
     #[serde(rename = "text")]
-    Text { text: String },
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
     #[serde(rename = "image")]
     Image {
         source: ImageSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
     },
     #[serde(rename = "document")]
     Document {
         source: DocumentSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    #[serde(rename = "thinking")]
+    Thinking { thinking: String, signature: String },
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+    #[serde(rename = "tool_use")]
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "tool_result")]
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ContentBlock {
+    fn text(text: String) -> Self {
+        ContentBlock::Text {
+            text,
+            cache_control: None,
+        }
+    }
+
+    /// The block's cache breakpoint slot; thinking and tool_use blocks can't carry one.
+    fn cache_control_mut(&mut self) -> Option<&mut Option<CacheControl>> {
+        match self {
+            ContentBlock::Text { cache_control, .. }
+            | ContentBlock::Image { cache_control, .. }
+            | ContentBlock::Document { cache_control, .. }
+            | ContentBlock::ToolResult { cache_control, .. } => Some(cache_control),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ImageSource {
     #[serde(rename = "type")]
     source_type: String,
@@ -57,7 +212,7 @@ struct ImageSource {
     data: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct DocumentSource {
     #[serde(rename = "type")]
     source_type: String,
@@ -65,108 +220,565 @@ struct DocumentSource {
     data: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AnthropicMessage {
     role: String,
     content: MessageContent,
 }
 
+impl AnthropicMessage {
+    /// Put a cache breakpoint on the message's last block that can take one,
+    /// or clear it with `None`.
+    fn set_cache_breakpoint(&mut self, cache_control: Option<CacheControl>) {
+        if let MessageContent::Text(text) = &self.content {
+            if cache_control.is_none() || text.is_empty() {
+                return;
+            }
+            self.content = MessageContent::Blocks(vec![ContentBlock::text(text.clone())]);
+        }
+
+        if let MessageContent::Blocks(blocks) = &mut self.content {
+            if let Some(slot) = blocks.iter_mut().rev().find_map(ContentBlock::cache_control_mut) {
+                *slot = cache_control;
+            }
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct StreamEvent {
     #[serde(rename = "type")]
     event_type: String,
     #[serde(default)]
+    index: Option<usize>,
+    #[serde(default)]
+    content_block: Option<serde_json::Value>,
+    #[serde(default)]
+    message: Option<StreamMessage>,
+    #[serde(default)]
     delta: Option<ContentDelta>,
+    #[serde(default)]
+    usage: Option<Usage>,
+    #[serde(default)]
+    error: Option<StreamError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    #[serde(default)]
+    usage: Option<Usage>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ContentDelta {
     #[serde(default)]
     text: Option<String>,
+    #[serde(default)]
+    thinking: Option<String>,
+    #[serde(default)]
+    signature: Option<String>,
+    /// Fragment of a tool_use block's input, from an `input_json_delta`
+    #[serde(default)]
+    partial_json: Option<String>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    #[serde(rename = "type", default)]
+    error_type: String,
+    message: String,
+}
+
+/// Turn a non-2xx response into an `AppError`, keeping the server's retry hint.
+async fn status_error(response: reqwest::Response) -> AppError {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let retry_after = retry_hint(&headers);
+    let error_text = response.text().await.unwrap_or_default();
+
+    AppError::from_response(status, &headers, &error_text, retry_after)
+}
+
+/// How long the server asked us to wait. `retry-after` wins; otherwise the
+/// latest reset among exhausted `anthropic-ratelimit-*` buckets is used.
+fn retry_hint(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(seconds) = headers
+        .get("retry-after")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<f64>().ok())
+    {
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+
+    let now = Utc::now();
+    ["requests", "tokens", "input-tokens", "output-tokens"]
+        .iter()
+        .filter(|bucket| {
+            headers
+                .get(format!("anthropic-ratelimit-{}-remaining", bucket))
+                .and_then(|v| v.to_str().ok())
+                == Some("0")
+        })
+        .filter_map(|bucket| {
+            let reset = headers
+                .get(format!("anthropic-ratelimit-{}-reset", bucket))?
+                .to_str()
+                .ok()?;
+            let reset = DateTime::parse_from_rfc3339(reset).ok()?;
+            (reset.with_timezone(&Utc) - now).to_std().ok()
+        })
+        .max()
+}
+
+/// Delay before retry number `attempt + 1`, or `None` to give up. A server
+/// hint longer than the policy allows is treated as a reason to stop.
+fn next_delay(policy: &RetryPolicy, attempt: u32, error: &AppError) -> Option<Duration> {
+    if !error.is_retryable() || attempt >= policy.max_retries {
+        return None;
+    }
+
+    let max_delay = Duration::from_millis(policy.max_delay_ms);
+    if let Some(hint) = error.retry_after() {
+        return (hint <= max_delay).then_some(hint);
+    }
+
+    // Exponential backoff with ±20% jitter so parallel chats don't retry in lockstep
+    let base = policy
+        .initial_delay_ms
+        .saturating_mul(2u64.saturating_pow(attempt))
+        .min(policy.max_delay_ms);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let jitter = 0.8 + 0.4 * (nanos % 1000) as f64 / 1000.0;
+
+    Some(Duration::from_millis((base as f64 * jitter) as u64).min(max_delay))
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u32,
+    #[serde(default)]
+    pub output_tokens: u32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u32,
+    /// Part of `cache_creation_input_tokens` written with the one-hour TTL
+    #[serde(default, rename(deserialize = "cache_creation"), deserialize_with = "one_hour_cache_writes")]
+    pub cache_creation_1h_input_tokens: u32,
+    #[serde(default)]
+    pub cache_read_input_tokens: u32,
+}
+
+/// Cache writes are broken down by TTL in a nested `cache_creation` object.
+fn one_hour_cache_writes<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<u32, D::Error> {
+    #[derive(Deserialize)]
+    struct CacheCreation {
+        #[serde(default)]
+        ephemeral_1h_input_tokens: u32,
+    }
+
+    Ok(Option::<CacheCreation>::deserialize(deserializer)?.map_or(0, |c| c.ephemeral_1h_input_tokens))
+}
+
+impl Usage {
+    pub fn is_empty(&self) -> bool {
+        self.input_tokens == 0
+            && self.output_tokens == 0
+            && self.cache_creation_input_tokens == 0
+            && self.cache_read_input_tokens == 0
+    }
+
+    /// `message_start` and `message_delta` of one response report cumulative
+    /// counts, so the larger value wins. Separate requests are added instead.
+    fn merge(&mut self, other: &Usage) {
+        self.input_tokens = self.input_tokens.max(other.input_tokens);
+        self.output_tokens = self.output_tokens.max(other.output_tokens);
+        self.cache_creation_input_tokens = self
+            .cache_creation_input_tokens
+            .max(other.cache_creation_input_tokens);
+        self.cache_creation_1h_input_tokens = self
+            .cache_creation_1h_input_tokens
+            .max(other.cache_creation_1h_input_tokens);
+        self.cache_read_input_tokens = self.cache_read_input_tokens.max(other.cache_read_input_tokens);
+    }
+
+    /// A usage record of `kind` for a request that is not a reply, such as a
+    /// title or summary, charged to the chat it was made for.
+    pub fn for_chat(&self, kind: &str, chat_id: &str, project_id: Option<String>, model: &str) -> MessageUsage {
+        MessageUsage {
+            message_id: Uuid::new_v4().to_string(),
+            chat_id: chat_id.to_string(),
+            kind: kind.to_string(),
+            project_id,
+            model: model.to_string(),
+            input_tokens: self.input_tokens.into(),
+            output_tokens: self.output_tokens.into(),
+            cache_creation_input_tokens: self.cache_creation_input_tokens.into(),
+            cache_creation_1h_input_tokens: self.cache_creation_1h_input_tokens.into(),
+            cache_read_input_tokens: self.cache_read_input_tokens.into(),
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    fn add(&self, other: &Usage) -> Usage {
+        Usage {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+            cache_creation_input_tokens: self.cache_creation_input_tokens + other.cache_creation_input_tokens,
+            cache_creation_1h_input_tokens: self.cache_creation_1h_input_tokens + other.cache_creation_1h_input_tokens,
+            cache_read_input_tokens: self.cache_read_input_tokens + other.cache_read_input_tokens,
+        }
+    }
 }
 
+/// Events sent to the frontend over the channel passed to `send_message`.
+/// Each generation gets its own channel, so concurrent chats never mix.
 #[derive(Debug, Clone, Serialize)]
-pub struct StreamChunk {
-    pub delta: String,
-    pub done: bool,
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GenerationEvent {
+    Start {
+        chat_id: String,
+        message_id: String,
+        model: String,
+    },
+    TextDelta {
+        delta: String,
+    },
+    ThinkingDelta {
+        delta: String,
+        signature: Option<String>,
+    },
+    Usage(Usage),
+    Retry {
+        attempt: u32,
+        max_retries: u32,
+        delay_ms: u64,
+        reason: String,
+    },
+    /// The model called a tool; sent once its input has been received in full
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        is_error: bool,
+    },
+    /// Older turns of the chat were left out, or summarised, to fit the context window
+    Compacted {
+        omitted_message_ids: Vec<String>,
+        summarized: bool,
+    },
+    Stop {
+        stop_reason: Option<String>,
+    },
+    Error {
+        error: AppError,
+    },
 }
 
-pub async fn stream_chat_completion(
-    app: AppHandle,
-    api_key: String,
-    settings: ProjectSettings,
-    messages: Vec<Message>,
+/// Receives the events of one generation: the UI's IPC channel, or the
+/// event stream of an HTTP API client.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: GenerationEvent);
+}
+
+impl EventSink for Channel<GenerationEvent> {
+    fn emit(&self, event: GenerationEvent) {
+        let _ = self.send(event);
+    }
+}
+
+/// Output of a streamed completion, filled in as deltas arrive so a cancelled
+/// or failed stream still leaves the partial reply behind.
+#[derive(Default)]
+pub struct Completion {
+    /// Text of every assistant turn, as shown to the user
+    pub content: String,
+    pub stop_reason: Option<String>,
+    /// Billed tokens summed over every request of the reply
+    pub usage: Usage,
+    /// Usage of the requests before the current one in a tool loop
+    previous_usage: Usage,
+    request_usage: Usage,
+    /// Blocks of the assistant turn being streamed, keyed by content block index
+    blocks: Vec<(usize, ContentBlock)>,
+    /// Raw `input_json_delta` fragments of the turn's tool_use blocks
+    tool_inputs: Vec<(usize, String)>,
+    /// Tool calls of the turn whose input didn't parse, by tool_use id, with the parse error
+    invalid_tool_inputs: Vec<(String, String)>,
+    /// Finished tool_use / tool_result turns
+    turns: Vec<AnthropicMessage>,
+}
+
+impl Completion {
+    /// JSON-encoded thinking and redacted_thinking blocks, in the order received
+    pub fn thinking_json(&self) -> Option<String> {
+        let earlier = self
+            .turns
+            .iter()
+            .filter(|turn| turn.role == "assistant")
+            .flat_map(|turn| match &turn.content {
+                MessageContent::Blocks(blocks) => blocks.iter().collect(),
+                MessageContent::Text(_) => Vec::new(),
+            });
+        let current = self.blocks.iter().map(|(_, block)| block);
+
+        let blocks: Vec<&ContentBlock> = earlier
+            .chain(current)
+            .filter(|block| matches!(block, ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. }))
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+
+        serde_json::to_string(&blocks).ok()
+    }
+
+    /// JSON-encoded API turns of a reply that called tools, ending with the
+    /// final answer. `None` when no tool was called.
+    pub fn tool_turns_json(&self) -> Option<String> {
+        if self.turns.is_empty() {
+            return None;
+        }
+
+        // A tool call without a result can't be replayed, so an unfinished round is dropped
+        let final_blocks: Vec<ContentBlock> = self
+            .turn_blocks()
+            .into_iter()
+            .filter(|block| !matches!(block, ContentBlock::ToolUse { .. }))
+            .collect();
+
+        let mut turns = self.turns.clone();
+        if !final_blocks.is_empty() {
+            turns.push(AnthropicMessage {
+                role: "assistant".to_string(),
+                content: MessageContent::Blocks(final_blocks),
+            });
+        }
+        serde_json::to_string(&turns).ok()
+    }
+
+    /// True until any text, thinking, redacted thinking or tool call has arrived.
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty() && self.turns.is_empty() && self.turn_is_empty()
+    }
+
+    fn turn_is_empty(&self) -> bool {
+        self.blocks.iter().all(|(_, block)| match block {
+            ContentBlock::Text { text, .. } => text.is_empty(),
+            ContentBlock::Thinking { thinking, .. } => thinking.is_empty(),
+            _ => false,
+        })
+    }
+
+    fn record_usage(&mut self, usage: &Usage) {
+        self.request_usage.merge(usage);
+        self.usage = self.previous_usage.add(&self.request_usage);
+    }
+
+    fn block_mut(&mut self, index: usize) -> Option<&mut ContentBlock> {
+        self.blocks
+            .iter_mut()
+            .find(|(i, _)| *i == index)
+            .map(|(_, block)| block)
+    }
+
+    /// Blocks of the current turn that can be sent back, in order.
+    fn turn_blocks(&self) -> Vec<ContentBlock> {
+        let mut blocks = self.blocks.clone();
+        blocks.sort_by_key(|(index, _)| *index);
+        blocks
+            .into_iter()
+            .map(|(_, block)| block)
+            .filter(|block| !matches!(block, ContentBlock::Text { text, .. } if text.is_empty()))
+            .collect()
+    }
+
+    /// Drop what a failed attempt left of the current turn and start counting
+    /// usage for the next request. What the attempt was billed is added to the
+    /// total, not merged with the next attempt's.
+    fn reset_turn(&mut self) {
+        self.blocks.clear();
+        self.tool_inputs.clear();
+        self.invalid_tool_inputs.clear();
+        self.stop_reason = None;
+        self.previous_usage = self.usage;
+        self.request_usage = Usage::default();
+    }
+
+    /// Close the current assistant turn and start counting usage for the next request.
+    fn finish_turn(&mut self) -> AnthropicMessage {
+        let turn = AnthropicMessage {
+            role: "assistant".to_string(),
+            content: MessageContent::Blocks(self.turn_blocks()),
+        };
+        self.reset_turn();
+        turn
+    }
+}
+
+/// Decode the thinking blocks stored on an assistant message so they can be
+/// replayed verbatim. Blocks without a signature are rejected by the API.
+fn stored_thinking_blocks(message: &Message) -> Vec<ContentBlock> {
+    let Some(thinking_json) = &message.thinking else {
+        return Vec::new();
+    };
+
+    serde_json::from_str::<Vec<ContentBlock>>(thinking_json)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|block| match block {
+            ContentBlock::Thinking { signature, .. } => !signature.is_empty(),
+            ContentBlock::RedactedThinking { .. } => true,
+            _ => false,
+        })
+        .collect()
+}
+
+/// Decode the turns stored on an assistant reply that called tools. Thinking
+/// blocks are dropped unless thinking is enabled, as for plain replies.
+fn stored_tool_turns(message: &Message, extended_thinking: bool) -> Option<Vec<AnthropicMessage>> {
+    if message.role != "assistant" {
+        return None;
+    }
+    let turns = serde_json::from_str::<Vec<AnthropicMessage>>(message.tool_turns.as_ref()?).ok()?;
+
+    let turns = turns
+        .into_iter()
+        .filter_map(|mut turn| {
+            if let MessageContent::Blocks(blocks) = &mut turn.content {
+                blocks.retain(|block| match block {
+                    ContentBlock::Thinking { signature, .. } => extended_thinking && !signature.is_empty(),
+                    ContentBlock::RedactedThinking { .. } => extended_thinking,
+                    _ => true,
+                });
+                if blocks.is_empty() {
+                    return None;
+                }
+            }
+            Some(turn)
+        })
+        .collect();
+    Some(turns)
+}
+
+/// Content block for a stored image or document, which must have its data loaded.
+fn attachment_block(attachment: &MessageAttachment) -> Option<ContentBlock> {
+    let data = attachment.data.clone()?;
+    let media_type = attachment.media_type.clone();
+    Some(match attachment.kind.as_str() {
+        "image" => ContentBlock::Image {
+            source: ImageSource {
+                source_type: "base64".to_string(),
+                media_type,
+                data,
+            },
+            cache_control: None,
+        },
+        // Text files go as text, so the model reads them like the rest of the prompt
+        _ if media_type == attachments::TEXT_MEDIA_TYPE => ContentBlock::Document {
+            source: DocumentSource {
+                source_type: "text".to_string(),
+                media_type,
+                data: attachments::text_for_model(attachment)?,
+            },
+            title: attachment.name.clone(),
+            cache_control: None,
+        },
+        _ => ContentBlock::Document {
+            source: DocumentSource {
+                source_type: "base64".to_string(),
+                media_type,
+                data,
+            },
+            title: attachment.name.clone(),
+            cache_control: None,
+        },
+    })
+}
+
+/// The request for a reply to `messages`, with the project's knowledge base
+/// and cache breakpoints in place.
+fn build_request(
+    settings: &ProjectSettings,
+    messages: &[Message],
+    knowledge: &[MessageAttachment],
     extended_thinking: bool,
-) -> Result<String> {
+    tools: &ToolRegistry,
+) -> AnthropicRequest {
     // Convert messages to Anthropic format
-    let anthropic_messages: Vec<AnthropicMessage> = messages
+    let mut anthropic_messages: Vec<AnthropicMessage> = messages
         .iter()
-        .map(|m| {
-            let has_images = m.images.as_ref().map(|s| !s.is_empty()).unwrap_or(false);
-            let has_documents = m.documents.as_ref().map(|s| !s.is_empty()).unwrap_or(false);
+        .flat_map(|m| {
+            // Replies that called tools are replayed turn by turn
+            if let Some(turns) = stored_tool_turns(m, extended_thinking) {
+                return turns;
+            }
+
+            // Signed thinking blocks are only sent back while thinking is enabled
+            let thinking_blocks = if extended_thinking && m.role == "assistant" {
+                stored_thinking_blocks(m)
+            } else {
+                Vec::new()
+            };
 
-            let content = if has_images || has_documents {
-                let mut blocks = Vec::new();
+            let content = if !m.attachments.is_empty() || !thinking_blocks.is_empty() {
+                // Thinking must precede the text of the assistant turn
+                let mut blocks = thinking_blocks;
 
                 // Add text first if present
                 if !m.content.is_empty() {
-                    blocks.push(ContentBlock::Text {
-                        text: m.content.clone(),
-                    });
+                    blocks.push(ContentBlock::text(m.content.clone()));
                 }
 
-                // Add images
-                if let Some(images_json) = &m.images {
-                    if let Ok(images) = serde_json::from_str::<Vec<serde_json::Value>>(images_json) {
-                        for image in images {
-                            if let (Some(data), Some(media_type)) = (
-                                image.get("data").and_then(|v| v.as_str()),
-                                image.get("media_type").and_then(|v| v.as_str()),
-                            ) {
-                                blocks.push(ContentBlock::Image {
-                                    source: ImageSource {
-                                        source_type: "base64".to_string(),
-                                        media_type: media_type.to_string(),
-                                        data: data.to_string(),
-                                    },
-                                });
-                            }
-                        }
-                    }
-                }
-
-                // Add documents
-                if let Some(documents_json) = &m.documents {
-                    if let Ok(documents) = serde_json::from_str::<Vec<serde_json::Value>>(documents_json) {
-                        for document in documents {
-                            if let (Some(data), Some(media_type)) = (
-                                document.get("data").and_then(|v| v.as_str()),
-                                document.get("media_type").and_then(|v| v.as_str()),
-                            ) {
-                                blocks.push(ContentBlock::Document {
-                                    source: DocumentSource {
-                                        source_type: "base64".to_string(),
-                                        media_type: media_type.to_string(),
-                                        data: data.to_string(),
-                                    },
-                                });
-                            }
-                        }
-                    }
-                }
+                // Add images and documents
+                blocks.extend(m.attachments.iter().filter_map(attachment_block));
 
                 MessageContent::Blocks(blocks)
             } else {
                 MessageContent::Text(m.content.clone())
             };
 
-            AnthropicMessage {
+            // The API rejects empty turns; older versions saved replies cancelled before any text
+            let empty = match &content {
+                MessageContent::Text(text) => text.is_empty(),
+                MessageContent::Blocks(blocks) => blocks.is_empty(),
+            };
+            if empty && m.role == "assistant" {
+                return Vec::new();
+            }
+
+            vec![AnthropicMessage {
                 role: m.role.clone(),
                 content,
-            }
+            }]
         })
         .collect();
 
+    let cache_control = CacheControl::new(&settings.prompt_cache);
+
+    // Project documents open the conversation, ahead of the first thing the user wrote
+    if let Some(first) = anthropic_messages.first_mut().filter(|_| !knowledge.is_empty()) {
+        let mut blocks: Vec<ContentBlock> = knowledge.iter().filter_map(attachment_block).collect();
+        // They rarely change, so they are cached apart from the conversation
+        if let Some(slot) = blocks.last_mut().and_then(ContentBlock::cache_control_mut) {
+            *slot = cache_control.clone();
+        }
+        match std::mem::replace(&mut first.content, MessageContent::Blocks(Vec::new())) {
+            MessageContent::Text(text) if !text.is_empty() => blocks.push(ContentBlock::text(text)),
+            MessageContent::Text(_) => {}
+            MessageContent::Blocks(existing) => blocks.extend(existing),
+        }
+        first.content = MessageContent::Blocks(blocks);
+    }
+
     let thinking_config = if extended_thinking {
         Some(ThinkingConfig {
             thinking_type: "enabled".to_string(),
@@ -176,38 +788,241 @@ pub async fn stream_chat_completion(
         None
     };
 
-    let request = AnthropicRequest {
-        model: settings.model,
+    // The system prompt, and with it the tool definitions before it, is cached on its own
+    let system = settings
+        .system_prompt
+        .clone()
+        .filter(|prompt| !prompt.is_empty())
+        .map(|text| ContentBlock::Text {
+            text,
+            cache_control: cache_control.clone(),
+        })
+        .into_iter()
+        .collect();
+
+    // The rolling breakpoint at the end of the conversation lets the next
+    // turn, or the next round of a tool loop, read everything before it back
+    if let Some(last) = anthropic_messages.last_mut() {
+        last.set_cache_breakpoint(cache_control.clone());
+    }
+
+    AnthropicRequest {
+        model: settings.model.clone(),
         max_tokens: settings.max_tokens,
         temperature: settings.temperature,
-        system: settings.system_prompt,
+        system,
         messages: anthropic_messages,
         stream: true,
         thinking: thinking_config,
+        tools: tools.definitions(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_chat_completion(
+    on_event: &dyn EventSink,
+    credentials: &ApiCredentials,
+    settings: ProjectSettings,
+    messages: Vec<Message>,
+    knowledge: Vec<MessageAttachment>,
+    extended_thinking: bool,
+    tools: &ToolRegistry,
+    cancel: Arc<Notify>,
+    completion: &mut Completion,
+) -> Result<()> {
+    let cache_control = CacheControl::new(&settings.prompt_cache);
+    let mut request = build_request(&settings, &messages, &knowledge, extended_thinking, tools);
+
+    let mut rounds = 0;
+
+    // Keep answering tool calls until the model finishes its reply
+    loop {
+        stream_with_retry(credentials, &request, &settings.retry, on_event, &cancel, completion).await?;
+
+        if completion.stop_reason.as_deref() != Some("tool_use") || rounds >= MAX_TOOL_ROUNDS {
+            break;
+        }
+        rounds += 1;
+
+        let invalid_inputs = std::mem::take(&mut completion.invalid_tool_inputs);
+        let assistant_turn = completion.finish_turn();
+        let (results, cancelled) = run_tools(tools, &assistant_turn, &invalid_inputs, on_event, &cancel).await;
+        if results.is_empty() {
+            break;
+        }
+
+        let results_turn = AnthropicMessage {
+            role: "user".to_string(),
+            content: MessageContent::Blocks(results),
+        };
+        // Move the rolling breakpoint to the new end; the API allows only four
+        if let Some(last) = request.messages.last_mut() {
+            last.set_cache_breakpoint(None);
+        }
+        request.messages.push(assistant_turn.clone());
+        request.messages.push(results_turn.clone());
+        if let Some(last) = request.messages.last_mut() {
+            last.set_cache_breakpoint(cache_control.clone());
+        }
+        completion.turns.push(assistant_turn);
+        completion.turns.push(results_turn);
+
+        if cancelled {
+            completion.stop_reason = Some("cancelled".to_string());
+            break;
+        }
+    }
+
+    on_event.emit(GenerationEvent::Stop {
+        stop_reason: completion.stop_reason.clone(),
+    });
+    Ok(())
+}
+
+/// One model request, retried according to `retry` while nothing of the
+/// current turn has reached the UI.
+async fn stream_with_retry(
+    credentials: &ApiCredentials,
+    request: &AnthropicRequest,
+    retry: &RetryPolicy,
+    on_event: &dyn EventSink,
+    cancel: &Notify,
+    completion: &mut Completion,
+) -> Result<()> {
+    let mut attempt = 0;
+
+    loop {
+        let error = match stream_attempt(credentials, request, on_event, cancel, completion).await {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+
+        // Retrying after output reached the UI would duplicate it
+        let delay = match next_delay(retry, attempt, &error) {
+            Some(delay) if completion.turn_is_empty() => delay,
+            _ => return Err(error),
+        };
+        attempt += 1;
+
+        // Drop empty blocks opened by the failed attempt
+        completion.reset_turn();
+
+        on_event.emit(GenerationEvent::Retry {
+            attempt,
+            max_retries: retry.max_retries,
+            delay_ms: delay.as_millis() as u64,
+            reason: error.message().to_string(),
+        });
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = cancel.notified() => {
+                completion.stop_reason = Some("cancelled".to_string());
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Run the tool calls of an assistant turn in order and return their
+/// tool_result blocks. Calls left when the user cancels are answered with an
+/// error, so the stored turns stay valid; the flag reports the cancellation.
+/// Calls whose input didn't parse are not run and answered with the error.
+async fn run_tools(
+    tools: &ToolRegistry,
+    turn: &AnthropicMessage,
+    invalid_inputs: &[(String, String)],
+    on_event: &dyn EventSink,
+    cancel: &Notify,
+) -> (Vec<ContentBlock>, bool) {
+    let MessageContent::Blocks(blocks) = &turn.content else {
+        return (Vec::new(), false);
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(ANTHROPIC_API_URL)
-        .header("x-api-key", api_key)
-        .header("anthropic-version", ANTHROPIC_VERSION)
-        .header("content-type", "application/json")
-        .json(&request)
-        .send()
-        .await?;
+    let mut results = Vec::new();
+    let mut cancelled = false;
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(AppError {
-            message: format!("API error: {}", error_text),
+    for block in blocks {
+        let ContentBlock::ToolUse { id, name, input } = block else {
+            continue;
+        };
+
+        let invalid_input = invalid_inputs.iter().find(|(tool_use_id, _)| tool_use_id == id);
+        let result = if cancelled {
+            None
+        } else if let Some((_, parse_error)) = invalid_input {
+            Some(Err(AppError::tool(format!("The tool input is not valid JSON ({}), so the tool was not run", parse_error))))
+        } else {
+            tokio::select! {
+                result = tools.call(name, input.clone()) => Some(result),
+                _ = cancel.notified() => None,
+            }
+        };
+
+        let (content, is_error) = match result {
+            Some(Ok(output)) => (output, false),
+            Some(Err(err)) => (err.message().to_string(), true),
+            None => {
+                cancelled = true;
+                ("Cancelled by the user".to_string(), true)
+            }
+        };
+
+        on_event.emit(GenerationEvent::ToolResult {
+            tool_use_id: id.clone(),
+            content: content.clone(),
+            is_error,
+        });
+        results.push(ContentBlock::ToolResult {
+            tool_use_id: id.clone(),
+            content,
+            is_error,
+            cache_control: None,
         });
     }
 
+    (results, cancelled)
+}
+
+/// One request to the Messages API, streamed into `completion`.
+async fn stream_attempt(
+    credentials: &ApiCredentials,
+    request: &AnthropicRequest,
+    on_event: &dyn EventSink,
+    cancel: &Notify,
+    completion: &mut Completion,
+) -> Result<()> {
+    let send = credentials.post(MESSAGES_PATH).json(request).send();
+
+    let response = tokio::select! {
+        response = send => response?,
+        _ = cancel.notified() => {
+            completion.stop_reason = Some("cancelled".to_string());
+            return Ok(());
+        }
+    };
+
+    if !response.status().is_success() {
+        return Err(status_error(response).await);
+    }
+
     let mut stream = response.bytes_stream();
-    let mut full_content = String::new();
     let mut buffer = String::new();
+    // A stream that ends without message_stop was cut off
+    let mut stopped = false;
+
+    loop {
+        let chunk = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = cancel.notified() => {
+                completion.stop_reason = Some("cancelled".to_string());
+                return Ok(());
+            }
+        };
 
-    while let Some(chunk) = stream.next().await {
+        let Some(chunk) = chunk else {
+            break;
+        };
         let chunk = chunk?;
         let text = String::from_utf8_lossy(&chunk);
         buffer.push_str(&text);
@@ -225,27 +1040,127 @@ pub async fn stream_chat_completion(
                     }
 
                     if let Ok(stream_event) = serde_json::from_str::<StreamEvent>(data) {
+                        let index = stream_event.index.unwrap_or(0);
+
                         match stream_event.event_type.as_str() {
+                            "message_start" => {
+                                if let Some(usage) = stream_event.message.and_then(|m| m.usage) {
+                                    completion.record_usage(&usage);
+                                    on_event.emit(GenerationEvent::Usage(completion.usage));
+                                }
+                            }
+                            "content_block_start" => {
+                                let block = stream_event
+                                    .content_block
+                                    .and_then(|v| serde_json::from_value::<ContentBlock>(v).ok());
+
+                                if let Some(block) = block {
+                                    if let ContentBlock::ToolUse { .. } = block {
+                                        completion.tool_inputs.push((index, String::new()));
+                                    }
+                                    completion.blocks.push((index, block));
+                                }
+                            }
                             "content_block_delta" => {
-                                if let Some(delta) = stream_event.delta {
-                                    if let Some(text) = delta.text {
-                                        full_content.push_str(&text);
-
-                                        // Emit to frontend
-                                        let _ = app.emit("stream_chunk", StreamChunk {
-                                            delta: text,
-                                            done: false,
+                                let Some(delta) = stream_event.delta else {
+                                    continue;
+                                };
+
+                                if let Some(text) = delta.text {
+                                    // Separate the text of successive turns in the displayed reply
+                                    let opens_block = matches!(
+                                        completion.block_mut(index),
+                                        Some(ContentBlock::Text { text, .. }) if text.is_empty()
+                                    );
+                                    let shown = if opens_block && !text.is_empty() && !completion.content.is_empty() {
+                                        format!("\n\n{}", text)
+                                    } else {
+                                        text.clone()
+                                    };
+
+                                    if let Some(ContentBlock::Text { text: block_text, .. }) = completion.block_mut(index) {
+                                        block_text.push_str(&text);
+                                    }
+                                    completion.content.push_str(&shown);
+
+                                    // Emit to frontend
+                                    on_event.emit(GenerationEvent::TextDelta { delta: shown });
+                                }
+
+                                if let Some(partial_json) = delta.partial_json {
+                                    if let Some((_, input)) = completion.tool_inputs.iter_mut().find(|(i, _)| *i == index) {
+                                        input.push_str(&partial_json);
+                                    }
+                                }
+
+                                if let Some(ContentBlock::Thinking { thinking, signature }) = completion.block_mut(index) {
+                                    if let Some(delta_thinking) = delta.thinking {
+                                        thinking.push_str(&delta_thinking);
+
+                                        on_event.emit(GenerationEvent::ThinkingDelta {
+                                            delta: delta_thinking,
+                                            signature: None,
+                                        });
+                                    }
+
+                                    if let Some(delta_signature) = delta.signature {
+                                        signature.push_str(&delta_signature);
+
+                                        on_event.emit(GenerationEvent::ThinkingDelta {
+                                            delta: String::new(),
+                                            signature: Some(signature.clone()),
                                         });
                                     }
                                 }
                             }
-                            "message_stop" => {
-                                // Emit completion
-                                let _ = app.emit("stream_chunk", StreamChunk {
-                                    delta: String::new(),
-                                    done: true,
-                                });
+                            "content_block_stop" => {
+                                // Tool input arrives as JSON fragments and is only valid once complete
+                                let Some(position) = completion.tool_inputs.iter().position(|(i, _)| *i == index) else {
+                                    continue;
+                                };
+                                let (_, raw_input) = completion.tool_inputs.remove(position);
+                                let parsed = if raw_input.trim().is_empty() {
+                                    Ok(serde_json::json!({}))
+                                } else {
+                                    serde_json::from_str(&raw_input)
+                                };
+
+                                if let Some(ContentBlock::ToolUse { id, name, input }) = completion.block_mut(index) {
+                                    // The call is kept with an empty input so the turn stays valid, but not run
+                                    let parse_error = parsed.as_ref().err().map(|e| (id.clone(), e.to_string()));
+                                    *input = parsed.unwrap_or_else(|_| serde_json::json!({}));
+                                    on_event.emit(GenerationEvent::ToolUse {
+                                        id: id.clone(),
+                                        name: name.clone(),
+                                        input: input.clone(),
+                                    });
+                                    completion.invalid_tool_inputs.extend(parse_error);
+                                }
+                            }
+                            "message_delta" => {
+                                if let Some(stop_reason) = stream_event.delta.and_then(|d| d.stop_reason) {
+                                    completion.stop_reason = Some(stop_reason);
+                                }
+
+                                if let Some(usage) = stream_event.usage {
+                                    completion.record_usage(&usage);
+                                    on_event.emit(GenerationEvent::Usage(completion.usage));
+                                }
                             }
+                            "error" => {
+                                let (error_type, message) = stream_event
+                                    .error
+                                    .map(|e| ((!e.error_type.is_empty()).then_some(e.error_type), e.message))
+                                    .unwrap_or_else(|| (None, "Unknown error".to_string()));
+                                return Err(AppError::from_api(ApiError {
+                                    message,
+                                    error_type,
+                                    status: None,
+                                    request_id: None,
+                                    retry_after_ms: None,
+                                }));
+                            }
+                            "message_stop" => stopped = true,
                             _ => {}
                         }
                     }
@@ -254,14 +1169,79 @@ pub async fn stream_chat_completion(
         }
     }
 
-    Ok(full_content)
+    if !stopped {
+        return Err(AppError::Network {
+            message: "The connection closed before the reply was complete".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Check a key, and the endpoint it goes to, with the cheapest authenticated request there is.
+pub async fn validate_credentials(credentials: &ApiCredentials) -> Result<()> {
+    let response = credentials.get(MODELS_PATH).query(&[("limit", "1")]).send().await?;
+    if !response.status().is_success() {
+        return Err(status_error(response).await);
+    }
+    Ok(())
+}
+
+/// Input tokens an image or document adds to a request, as counted by the API.
+pub async fn count_attachment_tokens(credentials: &ApiCredentials, model: &str, attachment: &MessageAttachment) -> Result<i64> {
+    let block = attachment_block(attachment).ok_or_else(|| AppError::invalid_input("Attachment has no content"))?;
+    let body = serde_json::json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": [block] }],
+    });
+    count_tokens(credentials, &body).await
+}
+
+/// Input tokens of the request `stream_chat_completion` would send, as counted by the API.
+pub async fn count_request_tokens(
+    credentials: &ApiCredentials,
+    settings: &ProjectSettings,
+    messages: &[Message],
+    knowledge: &[MessageAttachment],
+    extended_thinking: bool,
+    tools: &ToolRegistry,
+) -> Result<i64> {
+    let request = build_request(settings, messages, knowledge, extended_thinking, tools);
+    let mut body = serde_json::json!({
+        "model": request.model,
+        "messages": request.messages,
+    });
+    if !request.system.is_empty() {
+        body["system"] = serde_json::to_value(&request.system)?;
+    }
+    if !request.tools.is_empty() {
+        body["tools"] = serde_json::to_value(&request.tools)?;
+    }
+    if let Some(thinking) = &request.thinking {
+        body["thinking"] = serde_json::to_value(thinking)?;
+    }
+    count_tokens(credentials, &body).await
+}
+
+async fn count_tokens(credentials: &ApiCredentials, body: &serde_json::Value) -> Result<i64> {
+    let response = credentials.post(COUNT_TOKENS_PATH).json(body).send().await?;
+    if !response.status().is_success() {
+        return Err(status_error(response).await);
+    }
+
+    #[derive(Deserialize)]
+    struct TokenCount {
+        input_tokens: i64,
+    }
+
+    let count: TokenCount = response.json().await?;
+    Ok(count.input_tokens)
 }
 
 pub async fn generate_chat_title(
-    api_key: String,
+    credentials: &ApiCredentials,
     user_message: String,
     assistant_response: String,
-) -> Result<String> {
+) -> Result<(String, Usage)> {
     let prompt = format!(
         "Based on this conversation, generate a concise 3-5 word title that captures the main topic. Return ONLY the title, no quotes or extra text.\n\nUser: {}\n\nAssistant: {}",
         user_message,
@@ -269,51 +1249,90 @@ pub async fn generate_chat_title(
     );
 
     let request = AnthropicRequest {
-        model: "claude-3-5-sonnet-20241022".to_string(),
+        model: TITLE_MODEL.to_string(),
         max_tokens: 20,
         temperature: 0.5,
-        system: None,
+        system: Vec::new(),
         messages: vec![AnthropicMessage {
             role: "user".to_string(),
             content: MessageContent::Text(prompt),
         }],
         stream: false,
         thinking: None,
+        tools: Vec::new(),
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(ANTHROPIC_API_URL)
-        .header("x-api-key", api_key)
-        .header("anthropic-version", ANTHROPIC_VERSION)
-        .header("content-type", "application/json")
-        .json(&request)
-        .send()
-        .await?;
+    let (title, usage) = complete(credentials, &request).await?;
+    Ok((title.unwrap_or_else(|| "New Chat".to_string()), usage))
+}
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(AppError {
-            message: format!("API error: {}", error_text),
-        });
-    }
+/// Summarise the transcript of a conversation's older turns, so the rest of
+/// it can go on without them.
+pub async fn summarize_conversation(credentials: &ApiCredentials, model: &str, transcript: String) -> Result<(String, Usage)> {
+    let prompt = format!(
+        "Summarise the conversation below so it can be continued without the original messages. Keep the facts, decisions, code, names, numbers and open questions, and what the user has asked for or prefers. Write concise notes rather than a narrative, and return only the summary.\n\n<conversation>\n{}\n</conversation>",
+        transcript
+    );
+
+    let request = AnthropicRequest {
+        model: model.to_string(),
+        max_tokens: SUMMARY_MAX_TOKENS,
+        temperature: 0.0,
+        system: Vec::new(),
+        messages: vec![AnthropicMessage {
+            role: "user".to_string(),
+            content: MessageContent::Text(prompt),
+        }],
+        stream: false,
+        thinking: None,
+        tools: Vec::new(),
+    };
+
+    let (summary, usage) = complete(credentials, &request).await?;
+    let summary = summary.ok_or_else(|| AppError::invalid_input("The model returned an empty summary"))?;
+    Ok((summary, usage))
+}
+
+/// Send a non-streaming request, retrying with the default policy, and
+/// return the text of the reply's first block, with what it used.
+async fn complete(credentials: &ApiCredentials, request: &AnthropicRequest) -> Result<(Option<String>, Usage)> {
+    let retry = RetryPolicy::default();
+    let mut attempt = 0;
+
+    let response = loop {
+        let result = credentials.post(MESSAGES_PATH).json(request).send().await;
+
+        let error = match result {
+            Ok(response) if response.status().is_success() => break response,
+            Ok(response) => status_error(response).await,
+            Err(err) => AppError::from(err),
+        };
+
+        let Some(delay) = next_delay(&retry, attempt, &error) else {
+            return Err(error);
+        };
+        attempt += 1;
+        tokio::time::sleep(delay).await;
+    };
 
     #[derive(Deserialize)]
-    struct TitleResponse {
-        content: Vec<ContentBlock>,
+    struct CompletionResponse {
+        content: Vec<TextBlock>,
+        #[serde(default)]
+        usage: Usage,
     }
 
     #[derive(Deserialize)]
-    struct ContentBlock {
+    struct TextBlock {
+        #[serde(default)]
         text: String,
     }
 
-    let response_data: TitleResponse = response.json().await?;
-    let title = response_data
+    let response_data: CompletionResponse = response.json().await?;
+    let text = response_data
         .content
         .first()
         .map(|c| c.text.trim().to_string())
-        .unwrap_or_else(|| "New Chat".to_string());
-
-    Ok(title)
+        .filter(|text| !text.is_empty());
+    Ok((text, response_data.usage))
 }
@@ -0,0 +1,42 @@
+use crate::db::backup::{self, BackupConfig, BackupInfo};
+use crate::error::{AppError, Result};
+use crate::state::AppState;
+use std::path::Path;
+use tauri::{AppHandle, State};
+
+#[tauri::command]
+pub async fn create_backup(state: State<'_, AppState>, config: State<'_, Result<BackupConfig>>) -> Result<BackupInfo> {
+    Ok(backup::create_backup(&state.db, &settings(&config)?.dir, false).await?)
+}
+
+#[tauri::command]
+pub async fn list_backups(config: State<'_, Result<BackupConfig>>) -> Result<Vec<BackupInfo>> {
+    Ok(backup::list_backups(&settings(&config)?.dir)?)
+}
+
+/// Check the backup, stage it, and restart the app to swap it in. The
+/// current database is kept as `claude.db.before-restore`. Refused while a
+/// reply is being generated, as what it writes would be lost.
+#[tauri::command]
+pub async fn restore_backup(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    config: State<'_, Result<BackupConfig>>,
+    path: String,
+) -> Result<()> {
+    if state.has_generations() {
+        return Err(AppError::invalid_input(
+            "A reply is still being generated. Stop it or wait for it to finish, then restore the backup.",
+        ));
+    }
+    backup::stage_restore(&settings(&config)?.db_path, Path::new(&path)).await?;
+
+    // Any reply started while staging is stopped before the restart
+    state.stop_all_generations().await;
+    app.restart()
+}
+
+/// The backup settings, or the error that made them unusable.
+fn settings<'a>(config: &'a State<'_, Result<BackupConfig>>) -> Result<&'a BackupConfig> {
+    config.inner().as_ref().map_err(AppError::clone)
+}
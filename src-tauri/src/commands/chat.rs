@@ -1,9 +1,18 @@
-use crate::commands::anthropic::{self, stream_chat_completion};
+use crate::attachments;
+use crate::commands::api_credentials;
+use crate::commands::anthropic::{self, stream_chat_completion, Completion, EventSink, GenerationEvent};
+use crate::context;
 use crate::db;
-use crate::db::models::{Chat, Message, ProjectSettings};
+use crate::db::models::{Chat, Compaction, Message, MessageAttachment, MessageUsage, ProjectSettings};
 use crate::error::{AppError, Result};
-use crate::state::AppState;
-use tauri::{AppHandle, State};
+use crate::state::{AppState, Generation};
+use crate::tools::ToolRegistry;
+use base64::engine::general_purpose::STANDARD;
+use anyhow::Context;
+use base64::Engine;
+use std::path::Path;
+use tauri::ipc::Channel;
+use tauri::State;
 
 #[tauri::command]
 pub async fn list_chats(
@@ -45,12 +54,46 @@ pub async fn delete_chat(state: State<'_, AppState>, chat_id: String) -> Result<
     Ok(())
 }
 
+/// Messages on the active branch. Attachments are listed without their
+/// content unless `include_attachment_data` is set; `get_attachment` fetches
+/// one at a time.
 #[tauri::command]
-pub async fn list_messages(state: State<'_, AppState>, chat_id: String) -> Result<Vec<Message>> {
-    let messages = db::list_messages(&state.db, &chat_id).await?;
+pub async fn list_messages(
+    state: State<'_, AppState>,
+    chat_id: String,
+    include_attachment_data: Option<bool>,
+) -> Result<Vec<Message>> {
+    let mut messages = db::list_messages(&state.db, &chat_id).await?;
+    if include_attachment_data.unwrap_or(false) {
+        db::load_attachment_data(&state.db, &mut messages).await?;
+    }
     Ok(messages)
 }
 
+/// Summaries that stand in for the older turns of a long chat. Messages up
+/// to a compaction's `through_message_id` are no longer sent on that path.
+#[tauri::command]
+pub async fn list_compactions(state: State<'_, AppState>, chat_id: String) -> Result<Vec<Compaction>> {
+    let compactions = db::list_compactions(&state.db, &chat_id).await?;
+    Ok(compactions)
+}
+
+#[derive(serde::Serialize)]
+pub struct AttachmentData {
+    pub media_type: String,
+    /// Base64
+    pub data: String,
+}
+
+#[tauri::command]
+pub async fn get_attachment(state: State<'_, AppState>, sha256: String) -> Result<AttachmentData> {
+    let (media_type, bytes) = db::get_attachment(&state.db, &sha256).await?;
+    Ok(AttachmentData {
+        media_type,
+        data: STANDARD.encode(bytes),
+    })
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct MessageImage {
     data: String,
@@ -64,26 +107,14 @@ pub struct MessageDocument {
     name: String,
 }
 
-#[tauri::command]
-pub async fn send_message(
-    app: AppHandle,
-    state: State<'_, AppState>,
-    chat_id: String,
-    content: String,
-    project_id: Option<String>,
+/// Load the project's settings and apply per-message overrides.
+async fn resolve_settings(
+    state: &AppState,
+    project_id: &Option<String>,
     model: Option<String>,
-    images: Option<Vec<MessageImage>>,
-    extended_thinking: Option<bool>,
-    documents: Option<Vec<MessageDocument>>,
-) -> Result<Message> {
-    // Get API key
-    let api_key = state.get_api_key().ok_or_else(|| AppError {
-        message: "API key not configured. Please set ANTHROPIC_API_KEY environment variable."
-            .to_string(),
-    })?;
-
-    // Get settings
-    let mut settings = if let Some(pid) = &project_id {
+    extended_thinking: bool,
+) -> Result<ProjectSettings> {
+    let mut settings = if let Some(pid) = project_id {
         let project = db::get_project(&state.db, pid).await?;
         serde_json::from_str(&project.settings_json)?
     } else {
@@ -96,42 +127,398 @@ pub async fn send_message(
     }
 
     // Increase max_tokens if extended thinking is enabled
-    if extended_thinking.unwrap_or(false) && settings.max_tokens < 12000 {
+    if extended_thinking && settings.max_tokens < 12000 {
         settings.max_tokens = 16000;
     }
 
-    // Save user message with images, documents and metadata
-    let images_json = images.as_ref().map(|imgs| serde_json::to_string(imgs).ok()).flatten();
-    let documents_json = documents.as_ref().map(|docs| serde_json::to_string(docs).ok()).flatten();
+    Ok(settings)
+}
+
+/// Tools offered for a reply: the built-in ones if the project enables them,
+/// plus those of its MCP servers.
+async fn resolve_tools(state: &AppState, project_id: Option<&str>, settings: &ProjectSettings) -> ToolRegistry {
+    let mut tools = if settings.tools_enabled {
+        state.tools.clone()
+    } else {
+        ToolRegistry::default()
+    };
+
+    for server in &settings.mcp_servers {
+        match state.mcp.connect(project_id, server).await {
+            Ok(client) => client.register_tools(&mut tools),
+            // A broken server shouldn't block the chat; its tools are just not offered
+            Err(err) => eprintln!("Skipping MCP server '{}': {}", server.name, err),
+        }
+    }
+
+    tools
+}
+
+/// Stream a reply to the chat's active path and save it as a child of `parent_id`.
+#[allow(clippy::too_many_arguments)]
+async fn generate_reply(
+    state: &AppState,
+    generation: Generation,
+    on_event: &dyn EventSink,
+    chat_id: &str,
+    parent_id: &str,
+    project_id: Option<String>,
+    settings: ProjectSettings,
+    extended_thinking: bool,
+) -> Result<Message> {
+    // The project's credential profile, or the default key
+    let credentials = api_credentials(state, &settings).await?;
+
+    // Get message history along the active branch
+    let mut messages = db::list_messages(&state.db, chat_id).await?;
+    db::load_attachment_data(&state.db, &mut messages).await?;
+    let knowledge = match &project_id {
+        Some(pid) => db::load_project_knowledge(&state.db, pid).await?,
+        None => Vec::new(),
+    };
+
+    // The assistant message id is handed out up front so the UI can key the stream by it
+    let mut assistant_message = Message::new_assistant(chat_id.to_string(), String::new());
+    assistant_message.parent_id = Some(parent_id.to_string());
+    assistant_message.model = Some(settings.model.clone());
+    on_event.emit(GenerationEvent::Start {
+        chat_id: chat_id.to_string(),
+        message_id: assistant_message.id.clone(),
+        model: settings.model.clone(),
+    });
+
+    // Starting MCP servers can take a while, so it can be cancelled too
+    let tools = tokio::select! {
+        tools = resolve_tools(state, project_id.as_deref(), &settings) => tools,
+        _ = generation.cancel.notified() => {
+            // Passed on to the stream, which then stops before sending anything
+            generation.cancel.notify_one();
+            ToolRegistry::default()
+        }
+    };
+
+    // Long chats are cut down to what fits the model's context window
+    let fitted = context::fit_history(state, &credentials, &settings, chat_id, project_id.as_deref(), messages, &knowledge, &tools, extended_thinking).await;
+    if !fitted.omitted.is_empty() {
+        on_event.emit(GenerationEvent::Compacted {
+            omitted_message_ids: fitted.omitted,
+            summarized: fitted.summarized,
+        });
+    }
+
+    // Stream response from Claude
+    let mut completion = Completion::default();
+    let result = stream_chat_completion(
+        on_event,
+        &credentials,
+        settings,
+        fitted.messages,
+        knowledge,
+        extended_thinking,
+        &tools,
+        generation.cancel.clone(),
+        &mut completion,
+    )
+    .await;
+
+    if let Err(err) = &result {
+        completion.stop_reason = Some("error".to_string());
+        on_event.emit(GenerationEvent::Error { error: err.clone() });
+    }
+
+    // Save assistant message along with any signed thinking blocks and tool
+    // calls. A failed or cancelled stream keeps whatever partial text arrived;
+    // one that produced nothing is not saved, as an empty turn breaks the chat.
+    assistant_message.content = completion.content.clone();
+    assistant_message.thinking = completion.thinking_json();
+    assistant_message.tool_turns = completion.tool_turns_json();
+    assistant_message.stop_reason = completion.stop_reason.clone();
+
+    let mut saved = if completion.is_empty() {
+        Ok(())
+    } else {
+        db::create_message(&state.db, assistant_message.clone()).await
+    };
+
+    // Billed tokens are recorded even when the reply itself was not kept
+    if saved.is_ok() && !completion.usage.is_empty() {
+        let usage = MessageUsage {
+            message_id: assistant_message.id.clone(),
+            chat_id: chat_id.to_string(),
+            kind: "reply".to_string(),
+            project_id,
+            model: assistant_message.model.clone().unwrap_or_default(),
+            input_tokens: completion.usage.input_tokens.into(),
+            output_tokens: completion.usage.output_tokens.into(),
+            cache_creation_input_tokens: completion.usage.cache_creation_input_tokens.into(),
+            cache_creation_1h_input_tokens: completion.usage.cache_creation_1h_input_tokens.into(),
+            cache_read_input_tokens: completion.usage.cache_read_input_tokens.into(),
+            created_at: assistant_message.created_at.clone(),
+        };
+        saved = db::record_usage(&state.db, &usage).await;
+        assistant_message.usage = Some(usage);
+    }
+
+    drop(generation);
+    result?;
+    saved?;
+
+    Ok(assistant_message)
+}
+
+/// A user message to add to a chat, with per-message overrides for the reply.
+#[derive(serde::Deserialize)]
+pub struct NewMessage {
+    pub content: String,
+    /// Defaults to the chat's project
+    #[serde(default)]
+    pub project_id: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub images: Option<Vec<MessageImage>>,
+    #[serde(default)]
+    pub documents: Option<Vec<MessageDocument>>,
+    /// Images and PDFs to read from disk, rather than pass around as base64
+    #[serde(default)]
+    pub file_paths: Option<Vec<String>>,
+    #[serde(default)]
+    pub extended_thinking: bool,
+}
+
+/// Decode and check everything attached to a new message, scaling large
+/// images down. Reads files and decodes images, so it is run off the runtime.
+fn prepare_attachments(
+    images: Option<Vec<MessageImage>>,
+    documents: Option<Vec<MessageDocument>>,
+    paths: Option<Vec<String>>,
+) -> anyhow::Result<Vec<MessageAttachment>> {
+    let mut prepared = Vec::new();
+    for image in images.into_iter().flatten() {
+        let bytes = STANDARD.decode(image.data.trim()).context("Image is not valid base64")?;
+        prepared.push(attachments::prepare_image(bytes, None)?);
+    }
+    for document in documents.into_iter().flatten() {
+        let bytes = STANDARD.decode(document.data.trim()).context("Document is not valid base64")?;
+        prepared.push(attachments::prepare_document(bytes, document.name)?);
+    }
+    for path in paths.into_iter().flatten() {
+        prepared.push(attachments::read_file(Path::new(&path))?);
+    }
+    Ok(prepared)
+}
+
+/// Register a generation for the chat before anything is written, so a busy
+/// chat is left as it was.
+fn begin_generation(state: &AppState, chat_id: &str) -> Result<Generation> {
+    state
+        .begin_generation(chat_id)
+        .ok_or_else(|| AppError::busy("A response is already being generated for this chat"))
+}
+
+/// Append a user message to the end of the chat's active branch and stream a reply to it.
+pub async fn post_message(
+    state: &AppState,
+    on_event: &dyn EventSink,
+    chat_id: &str,
+    message: NewMessage,
+) -> Result<Message> {
+    let chat = db::get_chat(&state.db, chat_id).await?;
+    let generation = begin_generation(state, chat_id)?;
+    let project_id = message.project_id.or(chat.project_id);
+    let extended_thinking = message.extended_thinking;
+    let settings = resolve_settings(state, &project_id, message.model, extended_thinking).await?;
+
+    let (images, documents, paths) = (message.images, message.documents, message.file_paths);
+    let attachments = tokio::task::spawn_blocking(move || prepare_attachments(images, documents, paths))
+        .await
+        .map_err(|e| AppError::Internal { message: e.to_string() })?
+        .map_err(|e| AppError::invalid_input(format!("{:#}", e)))?;
+
+    // Save user message with attachments and metadata at the end of the active branch
+    let mut user_message = Message::new_user(chat_id.to_string(), message.content);
+    user_message.parent_id = chat.active_leaf_id;
+    user_message.attachments = attachments;
+    user_message.model = Some(settings.model.clone());
+    user_message.extended_thinking = Some(if extended_thinking { 1 } else { 0 });
+    db::create_message(&state.db, user_message.clone()).await?;
+
+    generate_reply(state, generation, on_event, chat_id, &user_message.id, project_id, settings, extended_thinking).await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_message(
+    state: State<'_, AppState>,
+    on_event: Channel<GenerationEvent>,
+    chat_id: String,
+    content: String,
+    project_id: Option<String>,
+    model: Option<String>,
+    images: Option<Vec<MessageImage>>,
+    extended_thinking: Option<bool>,
+    documents: Option<Vec<MessageDocument>>,
+    file_paths: Option<Vec<String>>,
+) -> Result<Message> {
+    let message = NewMessage {
+        content,
+        project_id,
+        model,
+        images,
+        documents,
+        file_paths,
+        extended_thinking: extended_thinking.unwrap_or(false),
+    };
+    post_message(&state, &on_event, &chat_id, message).await
+}
+
+/// Fork the thread at a past user message: the edited copy becomes a sibling
+/// of the original and gets a fresh reply. If no reply is saved, the branch
+/// that was active stays so.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn edit_message(
+    state: State<'_, AppState>,
+    on_event: Channel<GenerationEvent>,
+    chat_id: String,
+    message_id: String,
+    content: String,
+    project_id: Option<String>,
+    model: Option<String>,
+    extended_thinking: Option<bool>,
+) -> Result<Message> {
+    let original = db::get_message(&state.db, &message_id).await?;
+    if original.role != "user" || original.chat_id != chat_id {
+        return Err(AppError::invalid_input("Only user messages in this chat can be edited"));
+    }
+    let chat = db::get_chat(&state.db, &chat_id).await?;
+    let generation = begin_generation(&state, &chat_id)?;
+    let project_id = project_id.or(chat.project_id);
+
+    let extended_thinking = extended_thinking.unwrap_or(false);
+    let settings = resolve_settings(&state, &project_id, model, extended_thinking).await?;
+
+    // Attachments carry over to the edited copy
     let mut user_message = Message::new_user(chat_id.clone(), content);
-    user_message.images = images_json;
-    user_message.documents = documents_json;
+    user_message.parent_id = original.parent_id;
+    user_message.attachments = original.attachments;
     user_message.model = Some(settings.model.clone());
-    user_message.extended_thinking = Some(if extended_thinking.unwrap_or(false) { 1 } else { 0 });
+    user_message.extended_thinking = Some(if extended_thinking { 1 } else { 0 });
     db::create_message(&state.db, user_message.clone()).await?;
 
-    // Get message history
-    let messages = db::list_messages(&state.db, &chat_id).await?;
+    let reply =
+        generate_reply(&state, generation, &on_event, &chat_id, &user_message.id, project_id, settings, extended_thinking).await;
+    restore_leaf_if_unanswered(&state, &chat_id, &user_message.id, chat.active_leaf_id).await?;
+    reply
+}
 
-    // Stream response from Claude
-    let assistant_content = stream_chat_completion(app, api_key, settings, messages.clone(), extended_thinking.unwrap_or(false)).await?;
+/// Generate a new reply to the user message an assistant message answered.
+/// The previous reply stays available as a sibling branch, and stays active
+/// if no new reply is saved.
+#[tauri::command]
+pub async fn regenerate_message(
+    state: State<'_, AppState>,
+    on_event: Channel<GenerationEvent>,
+    chat_id: String,
+    message_id: String,
+    project_id: Option<String>,
+    model: Option<String>,
+    extended_thinking: Option<bool>,
+) -> Result<Message> {
+    let original = db::get_message(&state.db, &message_id).await?;
+    let parent_id = match (original.role.as_str(), original.parent_id) {
+        ("assistant", Some(parent_id)) if original.chat_id == chat_id => parent_id,
+        _ => {
+            return Err(AppError::invalid_input(
+                "Only assistant replies in this chat can be regenerated",
+            ))
+        }
+    };
+    let chat = db::get_chat(&state.db, &chat_id).await?;
+    let generation = begin_generation(&state, &chat_id)?;
+    let project_id = project_id.or(chat.project_id);
 
-    // Save assistant message
-    let assistant_message = Message::new_assistant(chat_id.clone(), assistant_content);
-    db::create_message(&state.db, assistant_message.clone()).await?;
+    let extended_thinking = extended_thinking.unwrap_or(false);
+    let settings = resolve_settings(&state, &project_id, model, extended_thinking).await?;
 
-    Ok(assistant_message)
+    // History for the new reply ends at the prompt being answered
+    db::set_active_leaf(&state.db, &chat_id, &parent_id).await?;
+
+    let reply = generate_reply(&state, generation, &on_event, &chat_id, &parent_id, project_id, settings, extended_thinking).await;
+    restore_leaf_if_unanswered(&state, &chat_id, &parent_id, chat.active_leaf_id).await?;
+    reply
+}
+
+/// Make `previous_leaf` active again when no reply to `prompt_id` was saved,
+/// so a failed or cancelled attempt doesn't leave the chat ending in an
+/// unanswered prompt.
+async fn restore_leaf_if_unanswered(
+    state: &AppState,
+    chat_id: &str,
+    prompt_id: &str,
+    previous_leaf: Option<String>,
+) -> Result<()> {
+    let chat = db::get_chat(&state.db, chat_id).await?;
+    match previous_leaf {
+        Some(previous_leaf) if chat.active_leaf_id.as_deref() == Some(prompt_id) => {
+            db::set_active_leaf(&state.db, chat_id, &previous_leaf).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// All versions of a message: itself and every sibling sharing its parent, oldest first.
+#[tauri::command]
+pub async fn list_branches(state: State<'_, AppState>, message_id: String) -> Result<Vec<Message>> {
+    let siblings = db::list_siblings(&state.db, &message_id).await?;
+    Ok(siblings)
 }
 
+/// Make the branch through `message_id` active, following the most recent
+/// reply at each fork below it. Returns the new active path.
+#[tauri::command]
+pub async fn switch_branch(
+    state: State<'_, AppState>,
+    chat_id: String,
+    message_id: String,
+) -> Result<Vec<Message>> {
+    let message = db::get_message(&state.db, &message_id).await?;
+    if message.chat_id != chat_id {
+        return Err(AppError::invalid_input("Message does not belong to this chat"));
+    }
+
+    let leaf_id = db::latest_leaf(&state.db, &message_id).await?;
+    db::set_active_leaf(&state.db, &chat_id, &leaf_id).await?;
+
+    let messages = db::list_messages(&state.db, &chat_id).await?;
+    Ok(messages)
+}
+
+#[tauri::command]
+pub async fn cancel_generation(state: State<'_, AppState>, chat_id: String) -> Result<bool> {
+    Ok(state.cancel_generation(&chat_id))
+}
+
+/// Title a chat from its first exchange, with the credentials of the chat's
+/// project. What it used is charged to the chat when there is one.
 #[tauri::command]
 pub async fn generate_title(
     state: State<'_, AppState>,
+    chat_id: Option<String>,
     user_message: String,
     assistant_response: String,
 ) -> Result<String> {
-    let api_key = state.get_api_key().ok_or_else(|| AppError {
-        message: "API key not configured".to_string(),
-    })?;
+    let project_id = match &chat_id {
+        Some(chat_id) => db::get_chat(&state.db, chat_id).await?.project_id,
+        None => None,
+    };
+    let settings = resolve_settings(&state, &project_id, None, false).await?;
+    let credentials = api_credentials(&state, &settings).await?;
 
-    anthropic::generate_chat_title(api_key, user_message, assistant_response).await
+    let (title, usage) = anthropic::generate_chat_title(&credentials, user_message, assistant_response).await?;
+    if let Some(chat_id) = &chat_id {
+        db::record_usage(&state.db, &usage.for_chat("title", chat_id, project_id, anthropic::TITLE_MODEL)).await?;
+    }
+    Ok(title)
 }
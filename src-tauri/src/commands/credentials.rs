@@ -0,0 +1,217 @@
+use crate::api_config;
+use crate::commands::anthropic::{self, ApiCredentials};
+use crate::db;
+use crate::db::models::{CredentialProfile, ProjectSettings};
+use crate::error::{AppError, Result};
+use crate::secrets::{self, SecretBackend, API_KEY};
+use crate::state::AppState;
+use std::collections::BTreeMap;
+use tauri::State;
+
+#[derive(serde::Serialize)]
+pub struct ApiKeyStatus {
+    pub configured: bool,
+    /// Last four characters of the key, to tell keys apart
+    pub hint: Option<String>,
+}
+
+#[tauri::command]
+pub async fn get_api_key_status(state: State<'_, AppState>) -> Result<ApiKeyStatus> {
+    let api_key = state.get_api_key();
+    Ok(ApiKeyStatus {
+        configured: api_key.is_some(),
+        hint: api_key.map(|key| key.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect()),
+    })
+}
+
+/// Store the key and use it for every request from now on. Reports whether
+/// it went into the OS keyring or the encrypted file.
+#[tauri::command]
+pub async fn set_api_key(state: State<'_, AppState>, api_key: String) -> Result<SecretBackend> {
+    let api_key = check_api_key(&api_key)?;
+    let store = state.secrets.clone();
+    let stored = api_key.clone();
+    let backend = blocking(move || store.set(API_KEY, &stored)).await?;
+    state.set_api_key(Some(api_key));
+    Ok(backend)
+}
+
+/// Forget the stored key. `ANTHROPIC_API_KEY` is used again if it is set.
+#[tauri::command]
+pub async fn clear_api_key(state: State<'_, AppState>) -> Result<()> {
+    let store = state.secrets.clone();
+    let api_key = blocking(move || {
+        store.delete(API_KEY)?;
+        Ok(secrets::load_api_key(&store))
+    })
+    .await?;
+    state.set_api_key(api_key);
+    Ok(())
+}
+
+/// Check a key with the API before saving it, or the current key if none is given.
+#[tauri::command]
+pub async fn validate_api_key(state: State<'_, AppState>, api_key: Option<String>) -> Result<()> {
+    let api_key = match api_key {
+        Some(api_key) => api_key.trim().to_string(),
+        None => state.get_api_key().ok_or_else(AppError::missing_api_key)?,
+    };
+    anthropic::validate_credentials(&state.api()?.credentials(api_key)).await
+}
+
+#[tauri::command]
+pub async fn list_credential_profiles(state: State<'_, AppState>) -> Result<Vec<CredentialProfile>> {
+    Ok(db::list_credential_profiles(&state.db).await?)
+}
+
+/// Add a named key. Projects use it once their settings name the profile.
+#[tauri::command]
+pub async fn create_credential_profile(
+    state: State<'_, AppState>,
+    name: String,
+    api_key: String,
+    base_url: Option<String>,
+    headers: Option<BTreeMap<String, String>>,
+) -> Result<CredentialProfile> {
+    let api_key = check_api_key(&api_key)?;
+    let name = check_name(&state, &name, None).await?;
+    let base_url = check_base_url(base_url)?;
+    let headers = check_headers(headers.unwrap_or_default())?;
+
+    let profile = db::create_credential_profile(&state.db, name, base_url, headers).await?;
+    if let Err(err) = store_profile_key(&state, &profile.id, api_key).await {
+        db::delete_credential_profile(&state.db, &profile.id).await?;
+        return Err(err);
+    }
+    Ok(profile)
+}
+
+/// Replace a profile's name, base URL and headers. The key is only replaced
+/// when a new one is given.
+#[tauri::command]
+pub async fn update_credential_profile(
+    state: State<'_, AppState>,
+    profile_id: String,
+    name: String,
+    api_key: Option<String>,
+    base_url: Option<String>,
+    headers: Option<BTreeMap<String, String>>,
+) -> Result<CredentialProfile> {
+    let mut profile = db::get_credential_profile(&state.db, &profile_id).await?;
+    let api_key = api_key.map(|key| check_api_key(&key)).transpose()?;
+    profile.name = check_name(&state, &name, Some(&profile_id)).await?;
+    profile.base_url = check_base_url(base_url)?;
+    profile.headers = check_headers(headers.unwrap_or_default())?;
+
+    if let Some(api_key) = api_key {
+        store_profile_key(&state, &profile_id, api_key).await?;
+    }
+    Ok(db::update_credential_profile(&state.db, &profile).await?)
+}
+
+/// Delete a profile and its key. Projects still bound to it fail to send
+/// until they are given another.
+#[tauri::command]
+pub async fn delete_credential_profile(state: State<'_, AppState>, profile_id: String) -> Result<()> {
+    let store = state.secrets.clone();
+    let name = secrets::profile_key(&profile_id);
+    blocking(move || store.delete(&name)).await?;
+    state.profile_keys.lock().remove(&profile_id);
+    db::delete_credential_profile(&state.db, &profile_id).await?;
+    Ok(())
+}
+
+/// Check a profile's key and endpoint with the API.
+#[tauri::command]
+pub async fn validate_credential_profile(state: State<'_, AppState>, profile_id: String) -> Result<()> {
+    let profile = db::get_credential_profile(&state.db, &profile_id).await?;
+    let credentials = profile_credentials(&state, profile).await?;
+    anthropic::validate_credentials(&credentials).await
+}
+
+/// What requests for a project with these settings authenticate with: its
+/// credential profile, or the default key when it has none.
+pub async fn api_credentials(state: &AppState, settings: &ProjectSettings) -> Result<ApiCredentials> {
+    let Some(profile_id) = &settings.credential_profile else {
+        let api_key = state.get_api_key().ok_or_else(AppError::missing_api_key)?;
+        return Ok(state.api()?.credentials(api_key));
+    };
+
+    let profile = match db::get_credential_profile(&state.db, profile_id).await.map_err(AppError::from) {
+        Err(AppError::NotFound { .. }) => {
+            return Err(AppError::invalid_input(
+                "This project's credential profile was deleted. Choose another in the project settings.",
+            ))
+        }
+        result => result?,
+    };
+    profile_credentials(state, profile).await
+}
+
+async fn profile_credentials(state: &AppState, profile: CredentialProfile) -> Result<ApiCredentials> {
+    // Read once, then kept in memory: keyring reads can prompt
+    let cached = state.profile_keys.lock().get(&profile.id).cloned();
+    let api_key = match cached {
+        Some(api_key) => api_key,
+        None => {
+            let store = state.secrets.clone();
+            let name = secrets::profile_key(&profile.id);
+            let api_key = blocking(move || store.get(&name)).await?.ok_or_else(|| AppError::MissingApiKey {
+                message: format!("No API key is saved for the credential profile '{}'.", profile.name),
+            })?;
+            state.profile_keys.lock().insert(profile.id.clone(), api_key.clone());
+            api_key
+        }
+    };
+
+    Ok(state.api()?.credentials(api_key).with_profile(profile.base_url, &profile.headers))
+}
+
+async fn store_profile_key(state: &AppState, profile_id: &str, api_key: String) -> Result<()> {
+    let store = state.secrets.clone();
+    let name = secrets::profile_key(profile_id);
+    let stored = api_key.clone();
+    blocking(move || store.set(&name, &stored)).await?;
+    state.profile_keys.lock().insert(profile_id.to_string(), api_key);
+    Ok(())
+}
+
+fn check_api_key(api_key: &str) -> Result<String> {
+    let api_key = api_key.trim();
+    if api_key.is_empty() || api_key.contains(char::is_whitespace) {
+        return Err(AppError::invalid_input("That doesn't look like an API key"));
+    }
+    Ok(api_key.to_string())
+}
+
+async fn check_name(state: &AppState, name: &str, profile_id: Option<&str>) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::invalid_input("Give the profile a name"));
+    }
+
+    let profiles = db::list_credential_profiles(&state.db).await?;
+    if profiles.iter().any(|p| p.name == name && Some(p.id.as_str()) != profile_id) {
+        return Err(AppError::invalid_input(format!("There is already a profile named '{}'", name)));
+    }
+    Ok(name.to_string())
+}
+
+fn check_base_url(base_url: Option<String>) -> Result<Option<String>> {
+    match base_url.map(|url| url.trim().to_string()).filter(|url| !url.is_empty()) {
+        Some(base_url) => Ok(Some(api_config::check_base_url(&base_url).map_err(|e| AppError::invalid_input(e.to_string()))?)),
+        None => Ok(None),
+    }
+}
+
+fn check_headers(headers: BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+    api_config::check_headers(headers).map_err(|e| AppError::invalid_input(e.to_string()))
+}
+
+/// Keyring calls block, and may sit behind an unlock prompt.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> anyhow::Result<T> + Send + 'static) -> Result<T> {
+    let result = tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| AppError::Internal { message: e.to_string() })?;
+    Ok(result?)
+}
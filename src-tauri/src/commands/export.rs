@@ -0,0 +1,439 @@
+use crate::db;
+use crate::db::models::{Chat, Message, MessageAttachment, Project};
+use crate::error::Result;
+use crate::markdown::{escape_html, fenced};
+use crate::state::AppState;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::fmt::Write;
+use tauri::State;
+
+/// Bumped when the layout of JSON archives changes
+const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Active branch as Markdown with YAML front-matter
+    Markdown,
+    /// Every message on every branch, attachments included
+    Json,
+    /// Active branch as a standalone page with images embedded
+    Html,
+}
+
+/// Lossless JSON export. Messages are stored as in the database, with
+/// attachment content inline and usage attached.
+#[derive(Serialize)]
+struct Archive {
+    version: u32,
+    exported_at: String,
+    project: Option<Project>,
+    chats: Vec<ChatArchive>,
+}
+
+#[derive(Serialize)]
+struct ChatArchive {
+    chat: Chat,
+    messages: Vec<Message>,
+}
+
+#[tauri::command]
+pub async fn export_chat(
+    state: State<'_, AppState>,
+    chat_id: String,
+    format: ExportFormat,
+    path: String,
+) -> Result<()> {
+    let document = render_chat(&state.db, &chat_id, format).await?;
+    std::fs::write(&path, document).with_context(|| format!("Failed to write {}", path))?;
+    Ok(())
+}
+
+/// Export all of a project's chats into a single file.
+#[tauri::command]
+pub async fn export_project(
+    state: State<'_, AppState>,
+    project_id: String,
+    format: ExportFormat,
+    path: String,
+) -> Result<()> {
+    let document = render_project(&state.db, &project_id, format).await?;
+    std::fs::write(&path, document).with_context(|| format!("Failed to write {}", path))?;
+    Ok(())
+}
+
+pub async fn render_chat(pool: &SqlitePool, chat_id: &str, format: ExportFormat) -> anyhow::Result<String> {
+    let chat = db::get_chat(pool, chat_id).await?;
+    let project = match &chat.project_id {
+        Some(project_id) => Some(db::get_project(pool, project_id).await?),
+        None => None,
+    };
+    render(pool, project, vec![chat], format, false).await
+}
+
+pub async fn render_project(pool: &SqlitePool, project_id: &str, format: ExportFormat) -> anyhow::Result<String> {
+    let project = db::get_project(pool, project_id).await?;
+    let chats = db::list_chats(pool, Some(project_id.to_string())).await?;
+    render(pool, Some(project), chats, format, true).await
+}
+
+async fn render(
+    pool: &SqlitePool,
+    project: Option<Project>,
+    chats: Vec<Chat>,
+    format: ExportFormat,
+    whole_project: bool,
+) -> anyhow::Result<String> {
+    let exported_at = Utc::now().to_rfc3339();
+
+    if let ExportFormat::Json = format {
+        let mut archived = Vec::with_capacity(chats.len());
+        for chat in chats {
+            let mut messages = db::list_all_messages(pool, &chat.id).await?;
+            db::load_attachment_data(pool, &mut messages).await?;
+            archived.push(ChatArchive { chat, messages });
+        }
+        let archive = Archive {
+            version: ARCHIVE_VERSION,
+            exported_at,
+            project,
+            chats: archived,
+        };
+        return Ok(serde_json::to_string_pretty(&archive)?);
+    }
+
+    let mut transcripts = Vec::with_capacity(chats.len());
+    for chat in chats {
+        let mut messages = db::list_messages(pool, &chat.id).await?;
+        // Only the HTML page embeds images
+        if let ExportFormat::Html = format {
+            db::load_attachment_data(pool, &mut messages).await?;
+        }
+        transcripts.push((chat, messages));
+    }
+
+    Ok(match format {
+        ExportFormat::Html => html_document(project.as_ref(), &transcripts, whole_project),
+        _ => markdown_document(project.as_ref(), &transcripts, whole_project, &exported_at),
+    })
+}
+
+// Message details shared by the Markdown and HTML renderers
+
+fn attachment_name(attachment: &MessageAttachment) -> &str {
+    attachment.name.as_deref().unwrap_or(&attachment.kind)
+}
+
+/// Thinking text in order; redacted blocks come back as `None`.
+fn thinking(message: &Message) -> Vec<Option<String>> {
+    let blocks: Vec<Value> = message
+        .thinking
+        .as_deref()
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or_default();
+
+    blocks
+        .iter()
+        .map(|block| block["thinking"].as_str().map(str::to_string))
+        .collect()
+}
+
+struct ToolCall {
+    name: String,
+    input: String,
+    result: Option<String>,
+    is_error: bool,
+}
+
+fn tool_calls(message: &Message) -> Vec<ToolCall> {
+    let turns: Vec<Value> = message
+        .tool_turns
+        .as_deref()
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or_default();
+    let blocks = || {
+        turns
+            .iter()
+            .filter_map(|turn| turn["content"].as_array())
+            .flatten()
+    };
+
+    blocks()
+        .filter(|block| block["type"] == "tool_use")
+        .map(|call| {
+            let result = blocks().find(|block| block["type"] == "tool_result" && block["tool_use_id"] == call["id"]);
+            ToolCall {
+                name: call["name"].as_str().unwrap_or_default().to_string(),
+                input: serde_json::to_string_pretty(&call["input"]).unwrap_or_default(),
+                result: result.and_then(|r| r["content"].as_str()).map(str::to_string),
+                is_error: result.is_some_and(|r| r["is_error"] == true),
+            }
+        })
+        .collect()
+}
+
+fn role_label(message: &Message) -> &'static str {
+    match message.role.as_str() {
+        "assistant" => "Assistant",
+        _ => "User",
+    }
+}
+
+/// Model, time and any unusual stop reason, e.g. "claude-sonnet-4-5 · 2025-01-31 14:05 UTC · cancelled".
+fn message_meta(message: &Message) -> String {
+    let mut parts = Vec::new();
+    if let Some(model) = &message.model {
+        if message.role == "assistant" {
+            parts.push(model.clone());
+        }
+    }
+    parts.push(display_time(&message.created_at));
+    if let Some(reason) = &message.stop_reason {
+        if reason != "end_turn" && reason != "tool_use" {
+            parts.push(reason.replace('_', " "));
+        }
+    }
+    parts.join(" · ")
+}
+
+fn display_time(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| time.with_timezone(&Utc).format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+fn models(messages: &[Message]) -> Vec<&str> {
+    let mut models: Vec<&str> = Vec::new();
+    for model in messages.iter().filter(|m| m.role == "assistant").filter_map(|m| m.model.as_deref()) {
+        if !models.contains(&model) {
+            models.push(model);
+        }
+    }
+    models
+}
+
+// Markdown
+
+/// A double-quoted YAML scalar; JSON string syntax is valid YAML.
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn markdown_document(
+    project: Option<&Project>,
+    transcripts: &[(Chat, Vec<Message>)],
+    whole_project: bool,
+    exported_at: &str,
+) -> String {
+    let mut out = String::from("---\n");
+    match (whole_project, project, transcripts.first()) {
+        (true, Some(project), _) => {
+            let _ = writeln!(out, "project: {}", yaml_string(&project.name));
+            let _ = writeln!(out, "project_id: {}", project.id);
+            let _ = writeln!(out, "created_at: {}", project.created_at);
+            let _ = writeln!(out, "chats: {}", transcripts.len());
+        }
+        (_, _, Some((chat, messages))) => {
+            let _ = writeln!(out, "title: {}", yaml_string(&chat.title));
+            let _ = writeln!(out, "chat_id: {}", chat.id);
+            if let Some(project) = project {
+                let _ = writeln!(out, "project: {}", yaml_string(&project.name));
+            }
+            let model_list: Vec<String> = models(messages).into_iter().map(yaml_string).collect();
+            let _ = writeln!(out, "models: [{}]", model_list.join(", "));
+            let _ = writeln!(out, "created_at: {}", chat.created_at);
+            let _ = writeln!(out, "updated_at: {}", chat.updated_at);
+        }
+        _ => {}
+    }
+    let _ = writeln!(out, "exported_at: {}\n---", exported_at);
+
+    for (chat, messages) in transcripts {
+        let _ = write!(out, "\n# {}\n", chat.title);
+        if whole_project {
+            let _ = writeln!(out, "\n*{} · {}*", chat.id, display_time(&chat.created_at));
+        }
+        for message in messages {
+            markdown_message(&mut out, message);
+        }
+    }
+    out
+}
+
+fn markdown_message(out: &mut String, message: &Message) {
+    let _ = write!(out, "\n## {}\n\n*{}*\n", role_label(message), message_meta(message));
+
+    for block in thinking(message) {
+        let text = block.unwrap_or_else(|| "*Redacted by the safety system*".to_string());
+        let _ = write!(out, "\n<details>\n<summary>Thinking</summary>\n\n{}\n\n</details>\n", text.trim());
+    }
+
+    for call in tool_calls(message) {
+        let _ = write!(
+            out,
+            "\n<details>\n<summary>Tool: {}</summary>\n\n{}\n",
+            escape_html(&call.name),
+            fenced(&call.input, "json")
+        );
+        if let Some(result) = &call.result {
+            let label = if call.is_error { "Error" } else { "Result" };
+            let _ = write!(out, "\n{}:\n\n{}\n", label, fenced(result, ""));
+        }
+        out.push_str("\n</details>\n");
+    }
+
+    if !message.attachments.is_empty() {
+        let names: Vec<String> = message
+            .attachments
+            .iter()
+            .map(|a| format!("{} ({})", attachment_name(a), a.media_type))
+            .collect();
+        let _ = write!(out, "\n*Attached: {}*\n", names.join(", "));
+    }
+
+    if !message.content.is_empty() {
+        let _ = write!(out, "\n{}\n", message.content.trim_end());
+    }
+}
+
+// HTML
+
+const STYLE: &str = "
+body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; line-height: 1.6; color: #1f2328; background: #f6f8fa; margin: 0; }
+main { max-width: 50rem; margin: 0 auto; padding: 2rem 1rem; }
+article + article { margin-top: 3rem; border-top: 1px solid #d0d7de; }
+.meta { color: #656d76; font-size: 0.85rem; }
+.message { background: #fff; border: 1px solid #d0d7de; border-radius: 8px; padding: 0.75rem 1rem; margin: 1rem 0; }
+.message.user { background: #eef4ff; }
+details { border-left: 3px solid #d0d7de; padding-left: 0.75rem; margin: 0.5rem 0; color: #424a53; }
+pre { background: #f6f8fa; padding: 0.75rem; border-radius: 6px; overflow-x: auto; }
+code { font-family: ui-monospace, SFMono-Regular, Menlo, monospace; font-size: 0.9em; }
+img { max-width: 100%; border-radius: 6px; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #d0d7de; padding: 0.25rem 0.5rem; }
+";
+
+/// Render Markdown, showing any raw HTML in it as text so the page stays inert.
+fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if dest_url.trim_start().to_ascii_lowercase().starts_with("javascript:") => Event::Start(Tag::Link {
+            link_type,
+            dest_url: CowStr::Borrowed("#"),
+            title,
+            id,
+        }),
+        event => event,
+    });
+
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out
+}
+
+fn html_document(project: Option<&Project>, transcripts: &[(Chat, Vec<Message>)], whole_project: bool) -> String {
+    let title = match (whole_project, project, transcripts.first()) {
+        (true, Some(project), _) => project.name.clone(),
+        (_, _, Some((chat, _))) => chat.title.clone(),
+        _ => "Export".to_string(),
+    };
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<main>\n",
+        escape_html(&title),
+        STYLE
+    );
+    if whole_project {
+        let _ = writeln!(out, "<h1>{}</h1>", escape_html(&title));
+    }
+
+    for (chat, messages) in transcripts {
+        let _ = write!(out, "<article>\n<h{0}>{1}</h{0}>\n", if whole_project { 2 } else { 1 }, escape_html(&chat.title));
+        let mut meta = vec![display_time(&chat.created_at)];
+        if let Some(project) = project.filter(|_| !whole_project) {
+            meta.insert(0, project.name.clone());
+        }
+        meta.extend(models(messages).into_iter().map(str::to_string));
+        let _ = writeln!(out, "<p class=\"meta\">{}</p>", escape_html(&meta.join(" · ")));
+
+        for message in messages {
+            html_message(&mut out, message);
+        }
+        out.push_str("</article>\n");
+    }
+
+    out.push_str("</main>\n</body>\n</html>\n");
+    out
+}
+
+fn html_message(out: &mut String, message: &Message) {
+    let _ = write!(
+        out,
+        "<section class=\"message {}\">\n<p><strong>{}</strong> <span class=\"meta\">{}</span></p>\n",
+        if message.role == "assistant" { "assistant" } else { "user" },
+        role_label(message),
+        escape_html(&message_meta(message))
+    );
+
+    for block in thinking(message) {
+        let body = match block {
+            Some(text) => markdown_to_html(&text),
+            None => "<p><em>Redacted by the safety system</em></p>".to_string(),
+        };
+        let _ = write!(out, "<details><summary>Thinking</summary>\n{}</details>\n", body);
+    }
+
+    for call in tool_calls(message) {
+        let _ = write!(
+            out,
+            "<details><summary>Tool: {}</summary>\n<pre><code>{}</code></pre>\n",
+            escape_html(&call.name),
+            escape_html(&call.input)
+        );
+        if let Some(result) = &call.result {
+            let label = if call.is_error { "Error" } else { "Result" };
+            let _ = write!(out, "<p>{}:</p>\n<pre><code>{}</code></pre>\n", label, escape_html(result));
+        }
+        out.push_str("</details>\n");
+    }
+
+    for attachment in &message.attachments {
+        match &attachment.data {
+            Some(data) if attachment.kind == "image" => {
+                let _ = writeln!(
+                    out,
+                    "<img src=\"data:{};base64,{}\" alt=\"{}\">",
+                    escape_html(&attachment.media_type),
+                    escape_html(data),
+                    escape_html(attachment_name(attachment))
+                );
+            }
+            _ => {
+                let _ = writeln!(
+                    out,
+                    "<p class=\"meta\">Attached: {} ({})</p>",
+                    escape_html(attachment_name(attachment)),
+                    escape_html(&attachment.media_type)
+                );
+            }
+        }
+    }
+
+    out.push_str(&markdown_to_html(&message.content));
+    out.push_str("</section>\n");
+}
@@ -0,0 +1,17 @@
+use crate::error::Result;
+use crate::import::{self, ImportSummary};
+use crate::state::AppState;
+use std::path::PathBuf;
+use tauri::State;
+
+/// Import a claude.ai or ChatGPT data export (the zip or its
+/// `conversations.json`) into `project_id`, or into a project named after
+/// the source.
+#[tauri::command]
+pub async fn import_conversations(
+    state: State<'_, AppState>,
+    path: String,
+    project_id: Option<String>,
+) -> Result<ImportSummary> {
+    Ok(import::import_file(&state.db, PathBuf::from(path), project_id).await?)
+}
@@ -0,0 +1,126 @@
+use crate::attachments;
+use crate::commands::anthropic::{self, ApiCredentials};
+use crate::commands::api_credentials;
+use crate::db;
+use crate::db::models::{MessageAttachment, ProjectDocument, ProjectSettings};
+use crate::error::{AppError, Result};
+use crate::state::AppState;
+use std::path::Path;
+use std::time::Duration;
+use tauri::State;
+
+/// How long listing documents waits for the ones not yet counted
+const COUNT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Content for a knowledge base document: a file to read, or text typed in
+/// under `name`.
+async fn read_content(path: Option<String>, text: Option<String>, name: Option<String>) -> Result<MessageAttachment> {
+    let content = tokio::task::spawn_blocking(move || match (path, text) {
+        (Some(path), None) => attachments::read_file(Path::new(&path)),
+        (None, Some(text)) => {
+            let name = name.unwrap_or_else(|| "Untitled.md".to_string());
+            attachments::prepare_document(text.into_bytes(), name)
+        }
+        _ => Err(anyhow::anyhow!("Give either a file path or text")),
+    })
+    .await
+    .map_err(|e| AppError::Internal { message: e.to_string() })?;
+
+    content.map_err(|e| AppError::invalid_input(format!("{:#}", e)))
+}
+
+/// The project's model and credentials, for counting. `None` when there is no key.
+async fn counter(state: &AppState, project_id: &str) -> Option<(String, ApiCredentials)> {
+    let project = db::get_project(&state.db, project_id).await.ok()?;
+    let settings = serde_json::from_str::<ProjectSettings>(&project.settings_json).unwrap_or_default();
+    let credentials = api_credentials(state, &settings).await.ok()?;
+    Some((settings.model, credentials))
+}
+
+/// Count a document's tokens with the project's model and record them. Best
+/// effort: without a key or a connection the count is left for the next listing.
+async fn count_tokens(state: &AppState, document: &mut ProjectDocument, content: &MessageAttachment) {
+    let Some((model, credentials)) = counter(state, &document.project_id).await else {
+        return;
+    };
+
+    match anthropic::count_attachment_tokens(&credentials, &model, content).await {
+        Ok(tokens) => {
+            if db::set_project_document_tokens(&state.db, &document.id, tokens).await.is_ok() {
+                document.token_count = Some(tokens);
+            }
+        }
+        Err(err) => eprintln!("Failed to count tokens for '{}': {}", document.name, err.message()),
+    }
+}
+
+/// Add a file, or text under `name`, to a project's knowledge base.
+#[tauri::command]
+pub async fn add_project_document(
+    state: State<'_, AppState>,
+    project_id: String,
+    path: Option<String>,
+    name: Option<String>,
+    text: Option<String>,
+) -> Result<ProjectDocument> {
+    let project = db::get_project(&state.db, &project_id).await?;
+    let content = read_content(path, text, name.clone()).await?;
+    let name = name.or_else(|| content.name.clone()).unwrap_or_else(|| "Untitled".to_string());
+
+    let mut document = db::create_project_document(&state.db, &project.id, name, &content).await?;
+    count_tokens(&state, &mut document, &content).await;
+    Ok(document)
+}
+
+/// The project's documents, oldest first. Any not yet counted are counted now,
+/// for up to `COUNT_TIMEOUT`; the rest are left for the next listing.
+#[tauri::command]
+pub async fn list_project_documents(state: State<'_, AppState>, project_id: String) -> Result<Vec<ProjectDocument>> {
+    let mut documents = db::list_project_documents(&state.db, &project_id).await?;
+    if counter(&state, &project_id).await.is_some() {
+        let counting = async {
+            for document in documents.iter_mut().filter(|d| d.token_count.is_none()) {
+                match stored_content(&state, document).await {
+                    Ok(content) => count_tokens(&state, document, &content).await,
+                    Err(err) => eprintln!("Failed to read '{}' for counting: {}", document.name, err.message()),
+                }
+            }
+        };
+        let _ = tokio::time::timeout(COUNT_TIMEOUT, counting).await;
+    }
+    Ok(documents)
+}
+
+/// Rename a document, replace its content from a file or text, or both.
+#[tauri::command]
+pub async fn update_project_document(
+    state: State<'_, AppState>,
+    document_id: String,
+    path: Option<String>,
+    name: Option<String>,
+    text: Option<String>,
+) -> Result<ProjectDocument> {
+    let existing = db::get_project_document(&state.db, &document_id).await?;
+    let content = match (&path, &text) {
+        (None, None) => None,
+        _ => Some(read_content(path, text, Some(name.clone().unwrap_or(existing.name))).await?),
+    };
+
+    let mut document = db::update_project_document(&state.db, &document_id, name, content.as_ref()).await?;
+    if let (Some(content), None) = (&content, document.token_count) {
+        count_tokens(&state, &mut document, content).await;
+    }
+    Ok(document)
+}
+
+#[tauri::command]
+pub async fn remove_project_document(state: State<'_, AppState>, document_id: String) -> Result<()> {
+    db::delete_project_document(&state.db, &document_id).await?;
+    Ok(())
+}
+
+async fn stored_content(state: &AppState, document: &ProjectDocument) -> Result<MessageAttachment> {
+    let (media_type, bytes) = db::get_attachment(&state.db, &document.sha256).await?;
+    let kind = if media_type.starts_with("image/") { "image" } else { "document" };
+    Ok(MessageAttachment::from_bytes(kind, media_type, Some(document.name.clone()), &bytes))
+}
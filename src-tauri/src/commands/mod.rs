@@ -1,6 +1,22 @@
 pub mod anthropic;
+pub mod backup;
 pub mod chat;
+pub mod credentials;
+pub mod export;
+pub mod import;
+pub mod knowledge;
 pub mod projects;
+pub mod search;
+pub mod tools;
+pub mod usage;
 
+pub use backup::*;
 pub use chat::*;
+pub use credentials::*;
+pub use export::*;
+pub use import::*;
+pub use knowledge::*;
 pub use projects::*;
+pub use search::*;
+pub use tools::*;
+pub use usage::*;
@@ -0,0 +1,30 @@
+use crate::db;
+use crate::db::models::SearchHit;
+use crate::error::Result;
+use crate::state::AppState;
+use tauri::State;
+
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+
+#[tauri::command]
+pub async fn search_messages(
+    state: State<'_, AppState>,
+    query: String,
+    project_id: Option<String>,
+    role: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<SearchHit>> {
+    let hits = db::search(
+        &state.db,
+        &query,
+        project_id,
+        role,
+        from,
+        to,
+        limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+    )
+    .await?;
+    Ok(hits)
+}
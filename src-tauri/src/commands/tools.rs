@@ -0,0 +1,29 @@
+use crate::db;
+use crate::db::models::ProjectSettings;
+use crate::error::Result;
+use crate::state::AppState;
+use crate::tools::{ToolDefinition, ToolRegistry};
+use tauri::State;
+
+/// Tools the model is offered in the project's chats. Unlike sending a
+/// message, this fails if one of the project's MCP servers can't be started,
+/// so the settings screen can show why.
+#[tauri::command]
+pub async fn list_project_tools(
+    state: State<'_, AppState>,
+    project_id: String,
+) -> Result<Vec<ToolDefinition>> {
+    let project = db::get_project(&state.db, &project_id).await?;
+    let settings: ProjectSettings = serde_json::from_str(&project.settings_json)?;
+
+    let mut tools = if settings.tools_enabled {
+        state.tools.clone()
+    } else {
+        ToolRegistry::default()
+    };
+    for server in &settings.mcp_servers {
+        state.mcp.connect(Some(&project_id), server).await?.register_tools(&mut tools);
+    }
+
+    Ok(tools.definitions())
+}
@@ -0,0 +1,49 @@
+use crate::db;
+use crate::db::models::{DailyUsage, ModelPrice, UsageTotals};
+use crate::error::Result;
+use crate::state::AppState;
+use tauri::State;
+
+#[tauri::command]
+pub async fn get_chat_usage(state: State<'_, AppState>, chat_id: String) -> Result<UsageTotals> {
+    let totals = db::chat_usage_totals(&state.db, &chat_id).await?;
+    Ok(totals)
+}
+
+#[tauri::command]
+pub async fn get_project_usage(
+    state: State<'_, AppState>,
+    project_id: Option<String>,
+) -> Result<UsageTotals> {
+    let totals = db::project_usage_totals(&state.db, project_id).await?;
+    Ok(totals)
+}
+
+#[tauri::command]
+pub async fn get_daily_usage(
+    state: State<'_, AppState>,
+    project_id: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<DailyUsage>> {
+    let days = db::daily_usage(&state.db, project_id, from, to).await?;
+    Ok(days)
+}
+
+#[tauri::command]
+pub async fn list_model_prices(state: State<'_, AppState>) -> Result<Vec<ModelPrice>> {
+    let prices = db::list_model_prices(&state.db).await?;
+    Ok(prices)
+}
+
+#[tauri::command]
+pub async fn set_model_price(state: State<'_, AppState>, price: ModelPrice) -> Result<()> {
+    db::set_model_price(&state.db, price).await?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_model_price(state: State<'_, AppState>, model: String) -> Result<()> {
+    db::delete_model_price(&state.db, &model).await?;
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use crate::attachments;
+use crate::commands::anthropic::{self, ApiCredentials, SUMMARY_MAX_TOKENS};
+use crate::db;
+use crate::db::models::{Compaction, ContextStrategy, Message, MessageAttachment, ProjectSettings};
+use crate::error::Result;
+use crate::state::AppState;
+use crate::tools::ToolRegistry;
+use chrono::Utc;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Context window of the current models, in tokens
+const DEFAULT_WINDOW_TOKENS: u32 = 200_000;
+/// Below this share of the budget the local estimate is trusted without asking the API
+const COUNT_THRESHOLD: f64 = 0.8;
+/// A new summary keeps only as many recent turns as fill this share of the
+/// budget, so it isn't redone on the very next turn
+const SUMMARY_KEEP_SHARE: f64 = 0.5;
+/// How often the API is asked to count before the history is sent as it stands
+const MAX_COUNTS: u32 = 3;
+/// What the API bills for an image at the size images are scaled down to
+const IMAGE_TOKENS: u64 = 1600;
+
+/// The history to send for a reply.
+pub struct Fitted {
+    pub messages: Vec<Message>,
+    /// Messages of the path that were left out, oldest first
+    pub omitted: Vec<String>,
+    /// Whether a summary stands in for the omitted messages
+    pub summarized: bool,
+}
+
+/// Fit a chat's history into the model's context window, leaving out or
+/// summarising the oldest turns as the project's settings say. Counting and
+/// summarising are best effort: when the API can't be reached the local
+/// estimate decides, and a failed summary falls back to leaving turns out.
+#[allow(clippy::too_many_arguments)]
+pub async fn fit_history(
+    state: &AppState,
+    credentials: &ApiCredentials,
+    settings: &ProjectSettings,
+    chat_id: &str,
+    project_id: Option<&str>,
+    messages: Vec<Message>,
+    knowledge: &[MessageAttachment],
+    tools: &ToolRegistry,
+    extended_thinking: bool,
+) -> Fitted {
+    let window = settings.context.window_tokens.unwrap_or(DEFAULT_WINDOW_TOKENS);
+    let budget = window.saturating_sub(settings.max_tokens) as f64;
+
+    // A stored summary replaces the messages it covers on every later turn
+    let (mut start, mut summary) = latest_compaction(state, chat_id, &messages).await;
+
+    let fixed = estimate_fixed(settings, knowledge, tools);
+    let sizes: Vec<u64> = messages.iter().map(estimate_tokens).collect();
+    let estimate = |start: usize, summary: Option<&str>| {
+        fixed + summary.map_or(0, |s| s.len() as u64 / 4) + sizes[start..].iter().sum::<u64>()
+    };
+
+    let mut scale = 1.0;
+    let mut summarized_now = false;
+    for _ in 0..MAX_COUNTS {
+        let estimated = estimate(start, summary.as_deref());
+        if estimated as f64 * scale <= budget * COUNT_THRESHOLD {
+            break;
+        }
+
+        // Close to the limit the API's count decides, and calibrates the estimate
+        let candidate = fitted(messages.clone(), start, summary.clone());
+        match anthropic::count_request_tokens(credentials, settings, &candidate.messages, knowledge, extended_thinking, tools).await {
+            Ok(count) if count as f64 <= budget => break,
+            Ok(count) => scale = count as f64 / estimated.max(1) as f64,
+            Err(err) if estimated as f64 <= budget => {
+                eprintln!("Failed to count tokens, sending as estimated: {}", err.message());
+                break;
+            }
+            Err(err) => eprintln!("Failed to count tokens, estimating instead: {}", err.message()),
+        }
+
+        let fits = |from: usize, extra: u64, limit: f64| (fixed + extra + sizes[from..].iter().sum::<u64>()) as f64 * scale <= limit;
+
+        if settings.context.strategy == ContextStrategy::Summarize && !summarized_now {
+            summarized_now = true;
+            let extra = SUMMARY_MAX_TOKENS as u64;
+            if let Some(cut) = cut_point(&messages, start, |from| fits(from, extra, budget * SUMMARY_KEEP_SHARE)) {
+                match summarize(state, credentials, settings, chat_id, project_id, &messages, start, cut, summary.as_deref(), budget).await {
+                    Ok(new_summary) => {
+                        start = cut;
+                        summary = Some(new_summary);
+                        continue;
+                    }
+                    Err(err) => eprintln!("Failed to summarise older turns, leaving them out instead: {}", err.message()),
+                }
+            }
+        }
+
+        let extra = summary.as_deref().map_or(0, |s| s.len() as u64 / 4);
+        match cut_point(&messages, start, |from| fits(from, extra, budget)) {
+            Some(cut) => start = cut,
+            None => break,
+        }
+    }
+
+    fitted(messages, start, summary)
+}
+
+/// The newest summary that covers a message of this path, and where the
+/// messages after it start.
+async fn latest_compaction(state: &AppState, chat_id: &str, messages: &[Message]) -> (usize, Option<String>) {
+    let compactions = match db::list_compactions(&state.db, chat_id).await {
+        Ok(compactions) => compactions,
+        Err(err) => {
+            eprintln!("Failed to load compactions for chat {}: {}", chat_id, err);
+            return (0, None);
+        }
+    };
+
+    compactions
+        .into_iter()
+        .filter_map(|compaction| {
+            let position = messages.iter().position(|m| m.id == compaction.through_message_id)?;
+            // The prompt being answered is never summarised away
+            (position + 1 < messages.len()).then_some((position + 1, Some(compaction.summary)))
+        })
+        .max_by_key(|(start, _)| *start)
+        .unwrap_or((0, None))
+}
+
+/// The earliest user turn after `start` from which the rest fits, or the
+/// last user turn if nothing does. `None` when there is no turn to cut at.
+fn cut_point(messages: &[Message], start: usize, fits: impl Fn(usize) -> bool) -> Option<usize> {
+    let turns: Vec<usize> = (start + 1..messages.len()).filter(|&i| messages[i].role == "user").collect();
+    turns.iter().copied().find(|&i| fits(i)).or(turns.last().copied())
+}
+
+/// Summarise `messages[start..cut]`, along with the summary before them, and
+/// store the result for later turns. What it used is charged to the chat.
+#[allow(clippy::too_many_arguments)]
+async fn summarize(
+    state: &AppState,
+    credentials: &ApiCredentials,
+    settings: &ProjectSettings,
+    chat_id: &str,
+    project_id: Option<&str>,
+    messages: &[Message],
+    start: usize,
+    cut: usize,
+    previous: Option<&str>,
+    budget: f64,
+) -> Result<String> {
+    // The summary request has to fit as well; the oldest part goes first
+    let max_chars = ((budget as u64).saturating_sub(SUMMARY_MAX_TOKENS as u64) * 3) as usize;
+    let transcript = transcript(previous, &messages[start..cut]);
+    let skip = transcript.len().saturating_sub(max_chars);
+    let skip = (skip..=transcript.len()).find(|&i| transcript.is_char_boundary(i)).unwrap_or(0);
+    let transcript = transcript[skip..].to_string();
+
+    let (summary, usage) = anthropic::summarize_conversation(credentials, &settings.model, transcript).await?;
+    db::record_usage(&state.db, &usage.for_chat("summary", chat_id, project_id.map(str::to_string), &settings.model)).await?;
+    let compaction = Compaction {
+        id: Uuid::new_v4().to_string(),
+        chat_id: chat_id.to_string(),
+        through_message_id: messages[cut - 1].id.clone(),
+        summary,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    db::create_compaction(&state.db, &compaction).await?;
+    Ok(compaction.summary)
+}
+
+/// Older turns as plain text for the model to summarise. Text files are
+/// included; images and PDFs are only named.
+fn transcript(previous: Option<&str>, messages: &[Message]) -> String {
+    let mut out = String::new();
+    if let Some(previous) = previous {
+        let _ = writeln!(out, "Summary of the conversation before this point:\n{}\n", previous);
+    }
+
+    for message in messages {
+        let speaker = if message.role == "user" { "User" } else { "Assistant" };
+        let _ = writeln!(out, "{}: {}", speaker, message.content);
+        for attachment in &message.attachments {
+            match attachments::text_for_model(attachment) {
+                Some(text) if attachment.media_type == attachments::TEXT_MEDIA_TYPE => {
+                    let _ = writeln!(out, "[Attached file] {}", text);
+                }
+                _ => {
+                    let name = attachment.name.as_deref().unwrap_or("unnamed");
+                    let _ = writeln!(out, "[Attached {}: {}]", attachment.kind, name);
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Drop the messages before `start`, opening the rest with the summary if there is one.
+fn fitted(mut messages: Vec<Message>, start: usize, summary: Option<String>) -> Fitted {
+    let omitted = messages.drain(..start).map(|m| m.id).collect();
+    let summarized = summary.is_some();
+    if let (Some(summary), Some(first)) = (summary, messages.first_mut()) {
+        first.content = format!(
+            "<summary>\nThe earlier part of this conversation, summarised:\n{}\n</summary>\n\n{}",
+            summary, first.content
+        );
+    }
+
+    Fitted {
+        messages,
+        omitted,
+        summarized,
+    }
+}
+
+/// Rough input tokens of a message: about four characters a token for text,
+/// a full-size image per image, and PDFs by size, which overestimates them.
+/// Near the limit the API's count decides, so this only needs to be close.
+fn estimate_tokens(message: &Message) -> u64 {
+    let text = message.content.len()
+        + message.thinking.as_ref().map_or(0, |t| t.len())
+        + message.tool_turns.as_ref().map_or(0, |t| t.len());
+    text as u64 / 4 + message.attachments.iter().map(attachment_tokens).sum::<u64>()
+}
+
+fn attachment_tokens(attachment: &MessageAttachment) -> u64 {
+    match attachment.kind.as_str() {
+        "image" => IMAGE_TOKENS,
+        _ => attachment.size_bytes as u64 / 4,
+    }
+}
+
+/// The system prompt, tool definitions and knowledge base, sent with every request.
+fn estimate_fixed(settings: &ProjectSettings, knowledge: &[MessageAttachment], tools: &ToolRegistry) -> u64 {
+    let system = settings.system_prompt.as_ref().map_or(0, |s| s.len());
+    let tools = serde_json::to_string(&tools.definitions()).map_or(0, |json| json.len());
+    (system + tools) as u64 / 4 + knowledge.iter().map(attachment_tokens).sum::<u64>()
+}
@@ -0,0 +1,277 @@
+use super::migrations;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use sqlx::{ConnectOptions, Connection};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const DIR_VAR: &str = "CLAUDE_LITE_BACKUP_DIR";
+const INTERVAL_VAR: &str = "CLAUDE_LITE_BACKUP_INTERVAL_HOURS";
+const KEEP_VAR: &str = "CLAUDE_LITE_BACKUP_KEEP";
+
+const DEFAULT_INTERVAL_HOURS: u64 = 24;
+const DEFAULT_KEEP: usize = 7;
+
+/// Where backups go and how often they are taken.
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    /// The live database, replaced on restore
+    pub db_path: PathBuf,
+    pub dir: PathBuf,
+    /// `None` turns scheduled backups off
+    pub interval: Option<Duration>,
+    /// Scheduled backups kept; older ones are deleted
+    pub keep: usize,
+}
+
+impl BackupConfig {
+    /// Defaults to a daily backup into `backups/` next to the database,
+    /// keeping a week's worth. `CLAUDE_LITE_BACKUP_INTERVAL_HOURS=0` turns
+    /// scheduling off.
+    pub fn from_env(db_path: PathBuf) -> Result<Self> {
+        let dir = match env::var(DIR_VAR) {
+            Ok(dir) if !dir.trim().is_empty() => PathBuf::from(dir.trim()),
+            _ => db_path.with_file_name("backups"),
+        };
+        let hours = match env::var(INTERVAL_VAR) {
+            Ok(hours) => hours
+                .trim()
+                .parse()
+                .with_context(|| format!("{} must be a whole number of hours", INTERVAL_VAR))?,
+            Err(_) => DEFAULT_INTERVAL_HOURS,
+        };
+        let keep = match env::var(KEEP_VAR) {
+            Ok(keep) => keep
+                .trim()
+                .parse()
+                .with_context(|| format!("{} must be a number", KEEP_VAR))?,
+            Err(_) => DEFAULT_KEEP,
+        };
+
+        Ok(Self {
+            db_path,
+            dir,
+            interval: (hours > 0).then(|| Duration::from_secs(hours * 3600)),
+            keep: keep.max(1),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub file_name: String,
+    pub size_bytes: u64,
+    pub created_at: String,
+    /// Taken by the schedule, and so subject to rotation
+    pub scheduled: bool,
+}
+
+const PREFIX: &str = "claude-";
+const MANUAL_SUFFIX: &str = "-manual.db";
+
+/// Write a consistent copy of the live database with `VACUUM INTO`, which is
+/// safe while other connections keep reading and writing.
+pub async fn create_backup(pool: &SqlitePool, dir: &Path, scheduled: bool) -> Result<BackupInfo> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create backup directory {:?}", dir))?;
+
+    // Milliseconds too, so backups taken within a second don't collide
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S-%3f");
+    let file_name = if scheduled {
+        format!("{}{}.db", PREFIX, stamp)
+    } else {
+        format!("{}{}{}", PREFIX, stamp, MANUAL_SUFFIX)
+    };
+    let path = dir.join(&file_name);
+    if path.exists() {
+        bail!("Backup {:?} already exists", path);
+    }
+
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().as_ref())
+        .execute(pool)
+        .await
+        .context("Failed to write backup")?;
+
+    backup_info(&path).context("Failed to read the new backup")
+}
+
+fn backup_info(path: &Path) -> Result<BackupInfo> {
+    let metadata = std::fs::metadata(path)?;
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let created_at: DateTime<Utc> = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH).into();
+
+    Ok(BackupInfo {
+        path: path.to_string_lossy().into_owned(),
+        scheduled: !file_name.ends_with(MANUAL_SUFFIX),
+        file_name,
+        size_bytes: metadata.len(),
+        created_at: created_at.to_rfc3339(),
+    })
+}
+
+/// Backups in the directory, newest first.
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read backup directory {:?}", dir)),
+    };
+
+    let mut backups: Vec<BackupInfo> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(".db"))
+        })
+        .filter_map(|path| backup_info(&path).ok())
+        .collect();
+
+    // Names carry the timestamp, so they sort by age
+    backups.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+    Ok(backups)
+}
+
+/// Delete all but the newest `keep` scheduled backups. Manual ones are left alone.
+pub fn prune_backups(dir: &Path, keep: usize) -> Result<()> {
+    for backup in list_backups(dir)?.iter().filter(|b| b.scheduled).skip(keep) {
+        std::fs::remove_file(&backup.path).with_context(|| format!("Failed to delete old backup {}", backup.path))?;
+    }
+    Ok(())
+}
+
+/// Take a backup whenever the newest scheduled one is older than the
+/// interval. Runs until the app exits.
+pub async fn run_schedule(pool: SqlitePool, config: BackupConfig) {
+    let Some(interval) = config.interval else {
+        return;
+    };
+
+    loop {
+        let newest = list_backups(&config.dir)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|b| b.scheduled)
+            .and_then(|b| std::fs::metadata(&b.path).ok()?.modified().ok())
+            .and_then(|modified| modified.elapsed().ok());
+
+        let wait = match newest {
+            Some(age) if age < interval => interval - age,
+            _ => {
+                let result = create_backup(&pool, &config.dir, true)
+                    .await
+                    .and_then(|_| prune_backups(&config.dir, config.keep));
+                if let Err(e) = result {
+                    eprintln!("Scheduled backup failed: {:#}", e);
+                }
+                interval
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Make sure a copy of a backup can be restored: its schema is one this
+/// build can migrate, and it passes SQLite's integrity check. The search
+/// indexes are rebuilt first, as they are keyed by rowids that `VACUUM INTO`
+/// may have renumbered.
+async fn prepare_restore(path: &Path) -> Result<()> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .connect()
+        .await
+        .with_context(|| format!("Failed to open backup {:?}", path))?;
+
+    let applied: Vec<(i64, String)> =
+        sqlx::query_as("SELECT version, checksum FROM schema_version ORDER BY version ASC")
+            .fetch_all(&mut conn)
+            .await
+            .context("Backup has no schema version; it is not a Claude Lite database")?;
+    if applied.is_empty() {
+        bail!("Backup has no schema version; it is not a Claude Lite database");
+    }
+    migrations::check_applied(&applied)?;
+
+    for table in ["messages_fts", "chats_fts"] {
+        let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM sqlite_master WHERE name = ?")
+            .bind(table)
+            .fetch_one(&mut conn)
+            .await?;
+        if exists {
+            sqlx::query(&format!("INSERT INTO {0} ({0}) VALUES ('rebuild')", table))
+                .execute(&mut conn)
+                .await?;
+        }
+    }
+
+    let integrity: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut conn)
+        .await?;
+    if integrity != ["ok"] {
+        bail!("Backup failed the integrity check: {}", integrity.join("; "));
+    }
+
+    conn.close().await?;
+    Ok(())
+}
+
+fn staged_path(db_path: &Path) -> PathBuf {
+    with_suffix(db_path, ".restore")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Check a copy of the backup and stage it next to the live database. The
+/// open pool can't have its file replaced underneath it, so the swap happens
+/// in `init_db` the next time the app starts.
+pub async fn stage_restore(db_path: &Path, backup: &Path) -> Result<()> {
+    if !backup.is_file() {
+        bail!("Backup {:?} does not exist", backup);
+    }
+
+    let staged = staged_path(db_path);
+    let partial = with_suffix(&staged, ".tmp");
+    std::fs::copy(backup, &partial).with_context(|| format!("Failed to copy {:?}", backup))?;
+    if let Err(e) = prepare_restore(&partial).await {
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let _ = std::fs::remove_file(with_suffix(&partial, suffix));
+        }
+        return Err(e);
+    }
+
+    std::fs::rename(&partial, &staged).context("Failed to stage backup")?;
+    Ok(())
+}
+
+/// Swap in a staged restore, if there is one. The replaced database and its
+/// WAL are kept as `claude.db.before-restore`.
+pub fn apply_staged_restore(db_path: &Path) -> Result<()> {
+    let staged = staged_path(db_path);
+    if !staged.exists() {
+        return Ok(());
+    }
+
+    let previous = with_suffix(db_path, ".before-restore");
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(with_suffix(&previous, suffix));
+        let current = with_suffix(db_path, suffix);
+        if current.exists() {
+            std::fs::rename(&current, with_suffix(&previous, suffix))
+                .with_context(|| format!("Failed to move {:?} aside", current))?;
+        }
+    }
+    std::fs::rename(&staged, db_path).context("Failed to move the restored database into place")?;
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use super::models::MessageAttachment;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqlitePool;
+use sqlx::SqliteConnection;
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+}
+
+// Append new migrations here. Never edit one that has shipped: its checksum
+// is recorded in every database it has been applied to.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "search",
+        sql: include_str!("migrations/0002_search.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "branching",
+        sql: include_str!("migrations/0003_branching.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "tool_use",
+        sql: include_str!("migrations/0004_tool_use.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "imports",
+        sql: include_str!("migrations/0005_imports.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "attachments",
+        sql: include_str!("migrations/0006_attachments.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "project_documents",
+        sql: include_str!("migrations/0007_project_documents.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "prompt_caching",
+        sql: include_str!("migrations/0008_prompt_caching.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "compactions",
+        sql: include_str!("migrations/0009_compactions.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "credential_profiles",
+        sql: include_str!("migrations/0010_credential_profiles.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "usage_kind",
+        sql: include_str!("migrations/0011_usage_kind.sql"),
+    },
+];
+
+fn checksum(sql: &str) -> String {
+    // Normalise line endings so a CRLF checkout hashes the same
+    let digest = Sha256::digest(sql.replace("\r\n", "\n").as_bytes());
+    format!("{:x}", digest)
+}
+
+/// Newest schema version this build knows how to produce.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Bring the database up to `latest_version`, one transaction per migration.
+pub async fn run(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create schema_version table")?;
+
+    let applied: Vec<(i64, String)> =
+        sqlx::query_as("SELECT version, checksum FROM schema_version ORDER BY version ASC")
+            .fetch_all(pool)
+            .await?;
+
+    check_applied(&applied)?;
+
+    let mut current = applied.last().map(|(v, _)| *v).unwrap_or(0);
+
+    if current == 0 && has_legacy_schema(pool).await? {
+        adopt_legacy_schema(pool).await?;
+        current = MIGRATIONS[0].version;
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let mut tx = pool.begin().await?;
+
+        sqlx::raw_sql(migration.sql)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| format!("Failed to apply migration {} ({})", migration.version, migration.name))?;
+
+        record(&mut tx, migration).await?;
+        tx.commit().await?;
+    }
+
+    move_inline_attachments(pool)
+        .await
+        .context("Failed to move attachments into the attachment store")?;
+
+    Ok(())
+}
+
+/// Fail unless this build can open a database with these `schema_version`
+/// rows: nothing newer than it knows, and every migration unchanged.
+pub fn check_applied(applied: &[(i64, String)]) -> Result<()> {
+    if let Some((newest, _)) = applied.last() {
+        if *newest > latest_version() {
+            bail!(
+                "Database schema version {} is newer than this app supports ({}). Please update Claude Lite.",
+                newest,
+                latest_version()
+            );
+        }
+    }
+
+    for (version, recorded) in applied {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.version == *version)
+            .with_context(|| format!("Unknown migration {} recorded in database", version))?;
+
+        if checksum(migration.sql) != *recorded {
+            bail!(
+                "Migration {} ({}) has been modified since it was applied",
+                migration.version,
+                migration.name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+struct InlineAttachment {
+    data: String,
+    media_type: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Move base64 images and documents written into message rows before
+/// migration 0006 into the attachment store. Runs until none are left, so an
+/// interrupted upgrade or a restored backup picks up where it stopped. The
+/// file is vacuumed afterwards to give the space back.
+async fn move_inline_attachments(pool: &SqlitePool) -> Result<()> {
+    let mut moved = false;
+    loop {
+        let rows: Vec<(String, Option<String>, Option<String>)> = sqlx::query_as(
+            "SELECT id, images, documents FROM messages \
+             WHERE images IS NOT NULL OR documents IS NOT NULL LIMIT 100",
+        )
+        .fetch_all(pool)
+        .await?;
+        if rows.is_empty() {
+            break;
+        }
+
+        for (message_id, images, documents) in rows {
+            let mut attachments = Vec::new();
+            for (kind, json) in [("image", images), ("document", documents)] {
+                // Unreadable entries could never be sent either, so they are dropped
+                let inline: Vec<InlineAttachment> = json
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default();
+                attachments.extend(inline.into_iter().filter_map(|a| {
+                    MessageAttachment::from_base64(kind, a.media_type, a.name, &a.data).ok()
+                }));
+            }
+
+            let mut tx = pool.begin().await?;
+            super::insert_attachments(&mut tx, &message_id, &attachments).await?;
+            sqlx::query("UPDATE messages SET images = NULL, documents = NULL WHERE id = ?")
+                .bind(&message_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        }
+        moved = true;
+    }
+
+    if moved {
+        sqlx::query("VACUUM").execute(pool).await?;
+        // VACUUM may renumber the rowids the search indexes are keyed by
+        sqlx::raw_sql(
+            "INSERT INTO messages_fts (messages_fts) VALUES ('rebuild'); \
+             INSERT INTO chats_fts (chats_fts) VALUES ('rebuild');",
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn record(conn: &mut SqliteConnection, migration: &Migration) -> Result<()> {
+    sqlx::query("INSERT INTO schema_version (version, name, checksum, applied_at) VALUES (?, ?, ?, ?)")
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(checksum(migration.sql))
+        .bind(Utc::now().to_rfc3339())
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Databases created before migrations existed have tables but no
+/// schema_version rows.
+async fn has_legacy_schema(pool: &SqlitePool) -> Result<bool> {
+    let exists: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'messages'",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(exists)
+}
+
+/// Upgrade a pre-migration database to the shape of migration 0001 and mark
+/// it as applied. The old bootstrap only ever created tables and added
+/// columns, so filling in whatever is missing is enough.
+async fn adopt_legacy_schema(pool: &SqlitePool) -> Result<()> {
+    let initial = &MIGRATIONS[0];
+    let mut tx = pool.begin().await?;
+
+    sqlx::raw_sql(initial.sql)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to upgrade legacy database schema")?;
+
+    add_column_if_missing(&mut tx, "messages", "thinking", "TEXT").await?;
+    add_column_if_missing(&mut tx, "messages", "stop_reason", "TEXT").await?;
+
+    record(&mut tx, initial).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn add_column_if_missing(
+    conn: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let exists: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to add column {}.{}", table, column))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+-- Claude Lite Database Schema
+-- Migration 0001: initial schema
+
+CREATE TABLE IF NOT EXISTS projects (
+  id TEXT PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL,
+  settings_json TEXT NOT NULL DEFAULT '{}',
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS chats (
+  id TEXT PRIMARY KEY NOT NULL,
+  project_id TEXT REFERENCES projects(id) ON DELETE CASCADE,
+  title TEXT NOT NULL DEFAULT 'New Chat',
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+  id TEXT PRIMARY KEY NOT NULL,
+  chat_id TEXT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+  role TEXT NOT NULL CHECK(role IN ('user', 'assistant')),
+  content TEXT NOT NULL,
+  images TEXT,
+  documents TEXT,
+  model TEXT,
+  extended_thinking INTEGER DEFAULT 0,
+  thinking TEXT,
+  stop_reason TEXT,
+  created_at TEXT NOT NULL
+);
+
+-- Token usage per assistant reply. Kept without foreign keys so spend
+-- history survives deleting the chat or project it came from.
+CREATE TABLE IF NOT EXISTS usage (
+  message_id TEXT PRIMARY KEY NOT NULL,
+  chat_id TEXT NOT NULL,
+  project_id TEXT,
+  model TEXT NOT NULL,
+  input_tokens INTEGER NOT NULL DEFAULT 0,
+  output_tokens INTEGER NOT NULL DEFAULT 0,
+  cache_creation_input_tokens INTEGER NOT NULL DEFAULT 0,
+  cache_read_input_tokens INTEGER NOT NULL DEFAULT 0,
+  created_at TEXT NOT NULL
+);
+
+-- Prices in USD per million tokens
+CREATE TABLE IF NOT EXISTS model_prices (
+  model TEXT PRIMARY KEY NOT NULL,
+  input_per_mtok REAL NOT NULL,
+  output_per_mtok REAL NOT NULL,
+  cache_write_per_mtok REAL NOT NULL,
+  cache_read_per_mtok REAL NOT NULL
+);
+
+INSERT OR IGNORE INTO model_prices (model, input_per_mtok, output_per_mtok, cache_write_per_mtok, cache_read_per_mtok) VALUES
+  ('claude-sonnet-4-5-20250929', 3.0, 15.0, 3.75, 0.30),
+  ('claude-sonnet-4-20250514', 3.0, 15.0, 3.75, 0.30),
+  ('claude-opus-4-1-20250805', 15.0, 75.0, 18.75, 1.50),
+  ('claude-opus-4-20250514', 15.0, 75.0, 18.75, 1.50);
+
+-- Indexes for better query performance
+CREATE INDEX IF NOT EXISTS idx_chats_project_id ON chats(project_id);
+CREATE INDEX IF NOT EXISTS idx_messages_chat_id ON messages(chat_id);
+CREATE INDEX IF NOT EXISTS idx_chats_updated_at ON chats(updated_at DESC);
+CREATE INDEX IF NOT EXISTS idx_projects_updated_at ON projects(updated_at DESC);
+CREATE INDEX IF NOT EXISTS idx_usage_chat_id ON usage(chat_id);
+CREATE INDEX IF NOT EXISTS idx_usage_project_id ON usage(project_id);
+CREATE INDEX IF NOT EXISTS idx_usage_created_at ON usage(created_at);
@@ -0,0 +1,48 @@
+-- Migration 0002: full-text search over message content and chat titles
+--
+-- Both indexes are external-content tables keyed by the source table's rowid.
+-- Run 'rebuild' on them after anything that may renumber rowids (VACUUM, restore).
+
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+  content,
+  content = 'messages',
+  content_rowid = 'rowid',
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE VIRTUAL TABLE chats_fts USING fts5(
+  title,
+  content = 'chats',
+  content_rowid = 'rowid',
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+  INSERT INTO messages_fts (rowid, content) VALUES (new.rowid, new.content);
+END;
+
+CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+  INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+END;
+
+CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+  INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+  INSERT INTO messages_fts (rowid, content) VALUES (new.rowid, new.content);
+END;
+
+CREATE TRIGGER chats_fts_insert AFTER INSERT ON chats BEGIN
+  INSERT INTO chats_fts (rowid, title) VALUES (new.rowid, new.title);
+END;
+
+CREATE TRIGGER chats_fts_delete AFTER DELETE ON chats BEGIN
+  INSERT INTO chats_fts (chats_fts, rowid, title) VALUES ('delete', old.rowid, old.title);
+END;
+
+CREATE TRIGGER chats_fts_update AFTER UPDATE OF title ON chats BEGIN
+  INSERT INTO chats_fts (chats_fts, rowid, title) VALUES ('delete', old.rowid, old.title);
+  INSERT INTO chats_fts (rowid, title) VALUES (new.rowid, new.title);
+END;
+
+-- Index everything written before this migration
+INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');
+INSERT INTO chats_fts (chats_fts) VALUES ('rebuild');
@@ -0,0 +1,26 @@
+-- Migration 0003: message tree for editing and regenerating past turns
+--
+-- Each message points at the one it follows; a chat's active_leaf_id picks
+-- which branch is shown and sent as history.
+
+ALTER TABLE messages ADD COLUMN parent_id TEXT REFERENCES messages(id) ON DELETE CASCADE;
+ALTER TABLE chats ADD COLUMN active_leaf_id TEXT REFERENCES messages(id) ON DELETE SET NULL;
+
+-- Existing chats become a single linear branch
+UPDATE messages SET parent_id = (
+  SELECT p.id FROM messages p
+  WHERE p.chat_id = messages.chat_id
+    AND (p.created_at < messages.created_at
+      OR (p.created_at = messages.created_at AND p.rowid < messages.rowid))
+  ORDER BY p.created_at DESC, p.rowid DESC
+  LIMIT 1
+);
+
+UPDATE chats SET active_leaf_id = (
+  SELECT m.id FROM messages m
+  WHERE m.chat_id = chats.id
+  ORDER BY m.created_at DESC, m.rowid DESC
+  LIMIT 1
+);
+
+CREATE INDEX IF NOT EXISTS idx_messages_parent_id ON messages(parent_id);
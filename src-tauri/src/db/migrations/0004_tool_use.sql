@@ -0,0 +1,7 @@
+-- Migration 0004: tool use
+--
+-- A reply that called tools is several API turns: assistant tool_use blocks,
+-- user tool_result blocks, and so on until the final answer. The full
+-- sequence is kept as JSON so it can be replayed verbatim.
+
+ALTER TABLE messages ADD COLUMN tool_turns TEXT;
@@ -0,0 +1,12 @@
+-- Migration 0005: imports from claude.ai and ChatGPT exports
+--
+-- Maps ids from an export to the projects, chats and messages created for
+-- them, so importing the same export again only adds what is new.
+
+CREATE TABLE IF NOT EXISTS import_sources (
+  source TEXT NOT NULL,
+  source_id TEXT NOT NULL,
+  local_id TEXT NOT NULL,
+  imported_at TEXT NOT NULL,
+  PRIMARY KEY (source, source_id)
+);
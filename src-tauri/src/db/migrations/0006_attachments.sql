@@ -0,0 +1,34 @@
+-- Migration 0006: content-addressed attachments
+--
+-- Image and document bytes are stored once per SHA-256 and referenced from
+-- messages, instead of as base64 JSON in every message row that carries
+-- them (edits and imports used to copy them wholesale). The old
+-- messages.images and messages.documents columns are left in place but no
+-- longer written; their contents are moved over in Rust after this runs,
+-- as SQLite has no built-in SHA-256.
+
+CREATE TABLE IF NOT EXISTS attachments (
+  sha256 TEXT PRIMARY KEY NOT NULL,
+  media_type TEXT NOT NULL,
+  size_bytes INTEGER NOT NULL,
+  data BLOB NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS message_attachments (
+  message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+  position INTEGER NOT NULL,
+  kind TEXT NOT NULL CHECK(kind IN ('image', 'document')),
+  sha256 TEXT NOT NULL REFERENCES attachments(sha256),
+  name TEXT,
+  PRIMARY KEY (message_id, position)
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_attachments_sha256 ON message_attachments(sha256);
+
+-- Blobs go when the last message referencing them does
+CREATE TRIGGER message_attachments_release AFTER DELETE ON message_attachments
+WHEN NOT EXISTS (SELECT 1 FROM message_attachments WHERE sha256 = old.sha256)
+BEGIN
+  DELETE FROM attachments WHERE sha256 = old.sha256;
+END;
@@ -0,0 +1,42 @@
+-- Migration 0007: project knowledge base
+--
+-- Reference documents owned by a project and sent with every chat in it.
+-- Their content lives in the attachment store next to message attachments,
+-- so a blob is only released once nothing of either kind refers to it.
+
+CREATE TABLE IF NOT EXISTS project_documents (
+  id TEXT PRIMARY KEY NOT NULL,
+  project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  sha256 TEXT NOT NULL REFERENCES attachments(sha256),
+  -- Counted by the API; NULL until it could be reached
+  token_count INTEGER,
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_project_documents_project_id ON project_documents(project_id);
+
+DROP TRIGGER IF EXISTS message_attachments_release;
+
+CREATE TRIGGER message_attachments_release AFTER DELETE ON message_attachments
+WHEN NOT EXISTS (SELECT 1 FROM message_attachments WHERE sha256 = old.sha256)
+  AND NOT EXISTS (SELECT 1 FROM project_documents WHERE sha256 = old.sha256)
+BEGIN
+  DELETE FROM attachments WHERE sha256 = old.sha256;
+END;
+
+CREATE TRIGGER project_documents_release AFTER DELETE ON project_documents
+WHEN NOT EXISTS (SELECT 1 FROM message_attachments WHERE sha256 = old.sha256)
+  AND NOT EXISTS (SELECT 1 FROM project_documents WHERE sha256 = old.sha256)
+BEGIN
+  DELETE FROM attachments WHERE sha256 = old.sha256;
+END;
+
+CREATE TRIGGER project_documents_replace AFTER UPDATE OF sha256 ON project_documents
+WHEN old.sha256 <> new.sha256
+  AND NOT EXISTS (SELECT 1 FROM message_attachments WHERE sha256 = old.sha256)
+  AND NOT EXISTS (SELECT 1 FROM project_documents WHERE sha256 = old.sha256)
+BEGIN
+  DELETE FROM attachments WHERE sha256 = old.sha256;
+END;
@@ -0,0 +1,12 @@
+-- Migration 0008: prompt caching
+--
+-- Cache writes with the one-hour TTL are billed at a higher rate than the
+-- default five-minute ones, so they are counted separately. The new column
+-- is the part of cache_creation_input_tokens written for an hour.
+
+ALTER TABLE usage ADD COLUMN cache_creation_1h_input_tokens INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE model_prices ADD COLUMN cache_write_1h_per_mtok REAL NOT NULL DEFAULT 0;
+
+-- Hour-long cache writes cost twice the base input price
+UPDATE model_prices SET cache_write_1h_per_mtok = input_per_mtok * 2;
@@ -0,0 +1,16 @@
+-- Migration 0009: history compaction
+--
+-- Summaries that stand in for the older part of a long chat. A summary
+-- covers the messages on the path up to and including through_message_id,
+-- so it goes away with that message.
+
+CREATE TABLE IF NOT EXISTS compactions (
+  id TEXT PRIMARY KEY NOT NULL,
+  chat_id TEXT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+  through_message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+  summary TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_compactions_chat_id ON compactions(chat_id);
+CREATE INDEX IF NOT EXISTS idx_compactions_through_message_id ON compactions(through_message_id);
@@ -0,0 +1,15 @@
+-- Migration 0010: credential profiles
+--
+-- Named API keys a project can be bound to, each with its own endpoint and
+-- default headers. The keys themselves live in the OS keyring or the
+-- encrypted secrets file, under the profile's id, never in this table.
+
+CREATE TABLE IF NOT EXISTS credential_profiles (
+  id TEXT PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL UNIQUE,
+  -- NULL for https://api.anthropic.com
+  base_url TEXT,
+  headers_json TEXT NOT NULL DEFAULT '{}',
+  created_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Migration 0011: usage kinds
+--
+-- Titles and conversation summaries are billed too, but aren't messages.
+-- Their rows carry a generated message_id and are told apart by kind, so
+-- message counts only include replies.
+
+ALTER TABLE usage ADD COLUMN kind TEXT NOT NULL DEFAULT 'reply';
@@ -1,13 +1,21 @@
+pub mod backup;
+mod migrations;
 pub mod models;
 
+use crate::markdown::escape_html;
 use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use chrono::Utc;
-use models::{Chat, Message, Project, ProjectSettings};
+use models::{
+    Chat, Compaction, CredentialProfile, DailyUsage, Message, MessageAttachment, MessageUsage, ModelPrice, Project, ProjectDocument, ProjectSettings,
+    SearchHit, UsageTotals,
+};
 use sqlx::sqlite::SqlitePool;
+use sqlx::SqliteConnection;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
-const SCHEMA: &str = include_str!("schema.sql");
-
 pub async fn init_db(db_path: PathBuf) -> Result<SqlitePool> {
     // Create parent directory if it doesn't exist
     if let Some(parent) = db_path.parent() {
@@ -15,17 +23,17 @@ pub async fn init_db(db_path: PathBuf) -> Result<SqlitePool> {
             .context("Failed to create database directory")?;
     }
 
+    backup::apply_staged_restore(&db_path).context("Failed to restore backup")?;
+
     // Use sqlite:// URL format with absolute path
     let db_url = format!("sqlite:{}?mode=rwc", db_path.display());
     let pool = SqlitePool::connect(&db_url)
         .await
         .context("Failed to connect to database")?;
 
-    // Run schema
-    sqlx::raw_sql(SCHEMA)
-        .execute(&pool)
+    migrations::run(&pool)
         .await
-        .context("Failed to initialize database schema")?;
+        .context("Failed to migrate database schema")?;
 
     Ok(pool)
 }
@@ -126,6 +134,7 @@ pub async fn create_chat(pool: &SqlitePool, project_id: Option<String>) -> Resul
         id,
         project_id,
         title,
+        active_leaf_id: None,
         created_at: now.clone(),
         updated_at: now,
     })
@@ -134,14 +143,14 @@ pub async fn create_chat(pool: &SqlitePool, project_id: Option<String>) -> Resul
 pub async fn list_chats(pool: &SqlitePool, project_id: Option<String>) -> Result<Vec<Chat>> {
     let chats = if let Some(pid) = project_id {
         sqlx::query_as::<_, Chat>(
-            "SELECT id, project_id, title, created_at, updated_at FROM chats WHERE project_id = ? ORDER BY updated_at DESC",
+            "SELECT id, project_id, title, active_leaf_id, created_at, updated_at FROM chats WHERE project_id = ? ORDER BY updated_at DESC",
         )
         .bind(pid)
         .fetch_all(pool)
         .await?
     } else {
         sqlx::query_as::<_, Chat>(
-            "SELECT id, project_id, title, created_at, updated_at FROM chats WHERE project_id IS NULL ORDER BY updated_at DESC",
+            "SELECT id, project_id, title, active_leaf_id, created_at, updated_at FROM chats WHERE project_id IS NULL ORDER BY updated_at DESC",
         )
         .fetch_all(pool)
         .await?
@@ -152,7 +161,7 @@ pub async fn list_chats(pool: &SqlitePool, project_id: Option<String>) -> Result
 
 pub async fn get_chat(pool: &SqlitePool, chat_id: &str) -> Result<Chat> {
     let chat = sqlx::query_as::<_, Chat>(
-        "SELECT id, project_id, title, created_at, updated_at FROM chats WHERE id = ?",
+        "SELECT id, project_id, title, active_leaf_id, created_at, updated_at FROM chats WHERE id = ?",
     )
     .bind(chat_id)
     .fetch_one(pool)
@@ -185,40 +194,815 @@ pub async fn delete_chat(pool: &SqlitePool, chat_id: &str) -> Result<()> {
 
 // Message queries
 pub async fn create_message(pool: &SqlitePool, message: Message) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    insert_message(&mut tx, &message).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// `create_message`, within the caller's transaction.
+pub async fn insert_message(conn: &mut SqliteConnection, message: &Message) -> Result<()> {
     let now = Utc::now().to_rfc3339();
 
     sqlx::query(
-        "INSERT INTO messages (id, chat_id, role, content, images, documents, model, extended_thinking, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO messages (id, chat_id, parent_id, role, content, model, extended_thinking, thinking, tool_turns, stop_reason, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(&message.id)
     .bind(&message.chat_id)
+    .bind(&message.parent_id)
     .bind(&message.role)
     .bind(&message.content)
-    .bind(&message.images)
-    .bind(&message.documents)
     .bind(&message.model)
-    .bind(&message.extended_thinking)
+    .bind(message.extended_thinking)
+    .bind(&message.thinking)
+    .bind(&message.tool_turns)
+    .bind(&message.stop_reason)
     .bind(&message.created_at)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
-    // Update chat's updated_at timestamp
-    sqlx::query("UPDATE chats SET updated_at = ? WHERE id = ?")
+    insert_attachments(conn, &message.id, &message.attachments).await?;
+
+    // New messages extend the active branch
+    sqlx::query("UPDATE chats SET active_leaf_id = ?, updated_at = ? WHERE id = ?")
+        .bind(&message.id)
         .bind(now)
         .bind(&message.chat_id)
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
 
     Ok(())
 }
 
+const MESSAGE_COLUMNS: &str = "m.id, m.chat_id, m.parent_id, m.role, m.content, m.model, m.extended_thinking, m.thinking, m.tool_turns, m.stop_reason, m.created_at";
+
+pub async fn get_message(pool: &SqlitePool, message_id: &str) -> Result<Message> {
+    let message = sqlx::query_as::<_, Message>(&format!(
+        "SELECT {} FROM messages m WHERE m.id = ?",
+        MESSAGE_COLUMNS
+    ))
+    .bind(message_id)
+    .fetch_one(pool)
+    .await?;
+
+    let mut messages = [message];
+    load_attachments(pool, &mut messages, false).await?;
+    let [message] = messages;
+    Ok(message)
+}
+
+/// Messages on the chat's active branch, root first.
 pub async fn list_messages(pool: &SqlitePool, chat_id: &str) -> Result<Vec<Message>> {
-    let messages = sqlx::query_as::<_, Message>(
-        "SELECT id, chat_id, role, content, images, documents, model, extended_thinking, created_at FROM messages WHERE chat_id = ? ORDER BY created_at ASC",
-    )
+    let mut messages = sqlx::query_as::<_, Message>(&format!(
+        "WITH RECURSIVE path(id, depth) AS ( \
+            SELECT active_leaf_id, 0 FROM chats WHERE id = ? \
+            UNION ALL \
+            SELECT m.parent_id, path.depth + 1 FROM messages m JOIN path ON m.id = path.id \
+            WHERE m.parent_id IS NOT NULL \
+         ) \
+         SELECT {} FROM messages m JOIN path ON m.id = path.id ORDER BY path.depth DESC",
+        MESSAGE_COLUMNS
+    ))
+    .bind(chat_id)
+    .fetch_all(pool)
+    .await?;
+
+    attach_usage(pool, chat_id, &mut messages).await?;
+    load_attachments(pool, &mut messages, false).await?;
+    Ok(messages)
+}
+
+/// Every message in the chat across all branches, oldest first.
+pub async fn list_all_messages(pool: &SqlitePool, chat_id: &str) -> Result<Vec<Message>> {
+    let mut messages = sqlx::query_as::<_, Message>(&format!(
+        "SELECT {} FROM messages m WHERE m.chat_id = ? ORDER BY m.created_at ASC, m.rowid ASC",
+        MESSAGE_COLUMNS
+    ))
     .bind(chat_id)
     .fetch_all(pool)
     .await?;
 
+    attach_usage(pool, chat_id, &mut messages).await?;
+    load_attachments(pool, &mut messages, false).await?;
     Ok(messages)
 }
+
+async fn attach_usage(pool: &SqlitePool, chat_id: &str, messages: &mut [Message]) -> Result<()> {
+    let mut usage: HashMap<String, MessageUsage> = list_chat_usage(pool, chat_id)
+        .await?
+        .into_iter()
+        .map(|u| (u.message_id.clone(), u))
+        .collect();
+
+    for message in messages {
+        message.usage = usage.remove(&message.id);
+    }
+    Ok(())
+}
+
+pub async fn set_active_leaf(pool: &SqlitePool, chat_id: &str, message_id: &str) -> Result<()> {
+    sqlx::query("UPDATE chats SET active_leaf_id = ? WHERE id = ?")
+        .bind(message_id)
+        .bind(chat_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// The message and every other message sharing its parent, oldest first.
+pub async fn list_siblings(pool: &SqlitePool, message_id: &str) -> Result<Vec<Message>> {
+    let mut siblings = sqlx::query_as::<_, Message>(&format!(
+        "SELECT {} FROM messages m JOIN messages target ON target.id = ? \
+         WHERE m.chat_id = target.chat_id AND m.parent_id IS target.parent_id \
+         ORDER BY m.created_at ASC, m.rowid ASC",
+        MESSAGE_COLUMNS
+    ))
+    .bind(message_id)
+    .fetch_all(pool)
+    .await?;
+
+    load_attachments(pool, &mut siblings, false).await?;
+    Ok(siblings)
+}
+
+/// Walk down from a message, taking the most recent child at each fork.
+pub async fn latest_leaf(pool: &SqlitePool, message_id: &str) -> Result<String> {
+    let leaf_id: String = sqlx::query_scalar(
+        "WITH RECURSIVE descend(id, depth) AS ( \
+            SELECT ?, 0 \
+            UNION ALL \
+            SELECT (SELECT c.id FROM messages c WHERE c.parent_id = descend.id \
+                    ORDER BY c.created_at DESC, c.rowid DESC LIMIT 1), descend.depth + 1 \
+            FROM descend WHERE descend.id IS NOT NULL \
+         ) \
+         SELECT id FROM descend WHERE id IS NOT NULL ORDER BY depth DESC LIMIT 1",
+    )
+    .bind(message_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(leaf_id)
+}
+
+// Attachment queries
+#[derive(sqlx::FromRow)]
+struct AttachmentRow {
+    message_id: String,
+    #[sqlx(flatten)]
+    attachment: MessageAttachment,
+    data: Option<Vec<u8>>,
+}
+
+/// Fill in each message's attachments, with their content only if `with_data`.
+async fn load_attachments(pool: &SqlitePool, messages: &mut [Message], with_data: bool) -> Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    let ids: Vec<&str> = messages.iter().map(|m| m.id.as_str()).collect();
+    let rows = sqlx::query_as::<_, AttachmentRow>(&format!(
+        "SELECT ma.message_id, ma.sha256, ma.kind, a.media_type, ma.name, a.size_bytes, {} AS data \
+         FROM message_attachments ma JOIN attachments a ON a.sha256 = ma.sha256 \
+         WHERE ma.message_id IN (SELECT value FROM json_each(?)) \
+         ORDER BY ma.message_id, ma.position",
+        if with_data { "a.data" } else { "NULL" }
+    ))
+    .bind(serde_json::to_string(&ids)?)
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_message: HashMap<String, Vec<MessageAttachment>> = HashMap::new();
+    for row in rows {
+        let mut attachment = row.attachment;
+        attachment.data = row.data.map(|bytes| STANDARD.encode(bytes));
+        by_message.entry(row.message_id).or_default().push(attachment);
+    }
+    for message in messages {
+        message.attachments = by_message.remove(&message.id).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Load the content of the messages' attachments, as needed to send them to the API.
+pub async fn load_attachment_data(pool: &SqlitePool, messages: &mut [Message]) -> Result<()> {
+    load_attachments(pool, messages, true).await
+}
+
+/// Store any new attachment content and link the attachments to a message, in order.
+async fn insert_attachments(
+    conn: &mut SqliteConnection,
+    message_id: &str,
+    attachments: &[MessageAttachment],
+) -> Result<()> {
+    for (position, attachment) in attachments.iter().enumerate() {
+        store_attachment(conn, attachment).await?;
+
+        sqlx::query(
+            "INSERT INTO message_attachments (message_id, position, kind, sha256, name) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(message_id)
+        .bind(position as i64)
+        .bind(&attachment.kind)
+        .bind(&attachment.sha256)
+        .bind(&attachment.name)
+        .execute(&mut *conn)
+        .await
+        .with_context(|| format!("Failed to attach {}", attachment.sha256))?;
+    }
+    Ok(())
+}
+
+/// Store an attachment's content, if it carries any and it isn't stored already.
+async fn store_attachment(conn: &mut SqliteConnection, attachment: &MessageAttachment) -> Result<()> {
+    let Some(data) = &attachment.data else {
+        return Ok(());
+    };
+
+    let bytes = STANDARD.decode(data).context("Attachment is not valid base64")?;
+    sqlx::query(
+        "INSERT OR IGNORE INTO attachments (sha256, media_type, size_bytes, data, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&attachment.sha256)
+    .bind(&attachment.media_type)
+    .bind(bytes.len() as i64)
+    .bind(bytes)
+    .bind(Utc::now().to_rfc3339())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Media type and content of a stored attachment.
+pub async fn get_attachment(pool: &SqlitePool, sha256: &str) -> Result<(String, Vec<u8>)> {
+    let attachment = sqlx::query_as("SELECT media_type, data FROM attachments WHERE sha256 = ?")
+        .bind(sha256)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(attachment)
+}
+
+// Project document queries
+const PROJECT_DOCUMENT_COLUMNS: &str = "d.id, d.project_id, d.name, d.sha256, a.media_type, a.size_bytes, d.token_count, d.created_at, d.updated_at";
+
+pub async fn create_project_document(
+    pool: &SqlitePool,
+    project_id: &str,
+    name: String,
+    content: &MessageAttachment,
+) -> Result<ProjectDocument> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+
+    store_attachment(&mut tx, content).await?;
+    sqlx::query(
+        "INSERT INTO project_documents (id, project_id, name, sha256, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(project_id)
+    .bind(&name)
+    .bind(&content.sha256)
+    .bind(&now)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    get_project_document(pool, &id).await
+}
+
+pub async fn list_project_documents(pool: &SqlitePool, project_id: &str) -> Result<Vec<ProjectDocument>> {
+    let documents = sqlx::query_as::<_, ProjectDocument>(&format!(
+        "SELECT {} FROM project_documents d JOIN attachments a ON a.sha256 = d.sha256 \
+         WHERE d.project_id = ? ORDER BY d.created_at ASC, d.rowid ASC",
+        PROJECT_DOCUMENT_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(documents)
+}
+
+pub async fn get_project_document(pool: &SqlitePool, document_id: &str) -> Result<ProjectDocument> {
+    let document = sqlx::query_as::<_, ProjectDocument>(&format!(
+        "SELECT {} FROM project_documents d JOIN attachments a ON a.sha256 = d.sha256 WHERE d.id = ?",
+        PROJECT_DOCUMENT_COLUMNS
+    ))
+    .bind(document_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(document)
+}
+
+/// Rename a document and/or swap in new content. New content needs counting again.
+pub async fn update_project_document(
+    pool: &SqlitePool,
+    document_id: &str,
+    name: Option<String>,
+    content: Option<&MessageAttachment>,
+) -> Result<ProjectDocument> {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+
+    if let Some(name) = name {
+        sqlx::query("UPDATE project_documents SET name = ?, updated_at = ? WHERE id = ?")
+            .bind(name)
+            .bind(&now)
+            .bind(document_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    if let Some(content) = content {
+        store_attachment(&mut tx, content).await?;
+        sqlx::query(
+            "UPDATE project_documents SET sha256 = ?, token_count = NULL, updated_at = ? \
+             WHERE id = ? AND sha256 <> ?",
+        )
+        .bind(&content.sha256)
+        .bind(&now)
+        .bind(document_id)
+        .bind(&content.sha256)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    get_project_document(pool, document_id).await
+}
+
+pub async fn set_project_document_tokens(pool: &SqlitePool, document_id: &str, token_count: i64) -> Result<()> {
+    sqlx::query("UPDATE project_documents SET token_count = ? WHERE id = ?")
+        .bind(token_count)
+        .bind(document_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_project_document(pool: &SqlitePool, document_id: &str) -> Result<()> {
+    sqlx::query("DELETE FROM project_documents WHERE id = ?")
+        .bind(document_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// A project's documents with their content, in the shape of attachments, ready to send.
+pub async fn load_project_knowledge(pool: &SqlitePool, project_id: &str) -> Result<Vec<MessageAttachment>> {
+    let rows: Vec<(String, String, String, i64, Vec<u8>)> = sqlx::query_as(
+        "SELECT d.sha256, d.name, a.media_type, a.size_bytes, a.data \
+         FROM project_documents d JOIN attachments a ON a.sha256 = d.sha256 \
+         WHERE d.project_id = ? ORDER BY d.created_at ASC, d.rowid ASC",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(sha256, name, media_type, size_bytes, data)| MessageAttachment {
+            sha256,
+            kind: if media_type.starts_with("image/") { "image" } else { "document" }.to_string(),
+            media_type,
+            name: Some(name),
+            size_bytes,
+            data: Some(STANDARD.encode(data)),
+        })
+        .collect())
+}
+
+// Compaction queries
+pub async fn create_compaction(pool: &SqlitePool, compaction: &Compaction) -> Result<()> {
+    sqlx::query("INSERT INTO compactions (id, chat_id, through_message_id, summary, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(&compaction.id)
+        .bind(&compaction.chat_id)
+        .bind(&compaction.through_message_id)
+        .bind(&compaction.summary)
+        .bind(&compaction.created_at)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// A chat's compactions on every branch, oldest first.
+pub async fn list_compactions(pool: &SqlitePool, chat_id: &str) -> Result<Vec<Compaction>> {
+    let compactions = sqlx::query_as::<_, Compaction>(
+        "SELECT id, chat_id, through_message_id, summary, created_at FROM compactions WHERE chat_id = ? ORDER BY created_at ASC",
+    )
+    .bind(chat_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(compactions)
+}
+
+// Search queries
+
+/// Turn free text into an FTS5 query: every word must match, the last one as a
+/// prefix so results update while typing. Quoting each word keeps FTS syntax
+/// characters in user input from causing errors.
+fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() {
+        return None;
+    }
+
+    Some(format!("{}*", terms.join(" ")))
+}
+
+/// Ranked search over message content and chat titles. Filters are optional;
+/// `from` and `to` are inclusive `YYYY-MM-DD` dates. A role filter excludes
+/// title matches.
+pub async fn search(
+    pool: &SqlitePool,
+    query: &str,
+    project_id: Option<String>,
+    role: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: u32,
+) -> Result<Vec<SearchHit>> {
+    let Some(fts) = fts_query(query) else {
+        return Ok(Vec::new());
+    };
+
+    let hits = sqlx::query_as::<_, SearchHit>(
+        "SELECT m.chat_id, c.title AS chat_title, c.project_id, m.id AS message_id, m.role, \
+            snippet(messages_fts, 0, char(2), char(3), '…', 16) AS snippet, \
+            m.created_at, bm25(messages_fts) AS rank \
+         FROM messages_fts \
+         JOIN messages m ON m.rowid = messages_fts.rowid \
+         JOIN chats c ON c.id = m.chat_id \
+         WHERE messages_fts MATCH ?1 \
+         AND (?2 IS NULL OR c.project_id = ?2) \
+         AND (?3 IS NULL OR m.role = ?3) \
+         AND (?4 IS NULL OR substr(m.created_at, 1, 10) >= ?4) \
+         AND (?5 IS NULL OR substr(m.created_at, 1, 10) <= ?5) \
+         UNION ALL \
+         SELECT c.id, c.title, c.project_id, NULL, NULL, \
+            snippet(chats_fts, 0, char(2), char(3), '…', 16), \
+            c.created_at, bm25(chats_fts) \
+         FROM chats_fts \
+         JOIN chats c ON c.rowid = chats_fts.rowid \
+         WHERE chats_fts MATCH ?1 \
+         AND ?3 IS NULL \
+         AND (?2 IS NULL OR c.project_id = ?2) \
+         AND (?4 IS NULL OR substr(c.created_at, 1, 10) >= ?4) \
+         AND (?5 IS NULL OR substr(c.created_at, 1, 10) <= ?5) \
+         ORDER BY rank ASC \
+         LIMIT ?6",
+    )
+    .bind(fts)
+    .bind(project_id)
+    .bind(role)
+    .bind(from)
+    .bind(to)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(hits.into_iter().map(|hit| SearchHit { snippet: mark_snippet(&hit.snippet), ..hit }).collect())
+}
+
+/// Escape a snippet's text, then turn the match markers from `search` into
+/// `<mark>` tags.
+fn mark_snippet(snippet: &str) -> String {
+    escape_html(snippet).replace('\u{2}', "<mark>").replace('\u{3}', "</mark>")
+}
+
+// Usage queries
+const USAGE_TOTALS_COLUMNS: &str = "COALESCE(SUM(u.kind = 'reply'), 0) AS message_count, \
+    COALESCE(SUM(u.input_tokens), 0) AS input_tokens, \
+    COALESCE(SUM(u.output_tokens), 0) AS output_tokens, \
+    COALESCE(SUM(u.cache_creation_input_tokens), 0) AS cache_creation_input_tokens, \
+    COALESCE(SUM(u.cache_creation_1h_input_tokens), 0) AS cache_creation_1h_input_tokens, \
+    COALESCE(SUM(u.cache_read_input_tokens), 0) AS cache_read_input_tokens, \
+    COALESCE(SUM((u.input_tokens * p.input_per_mtok \
+        + u.output_tokens * p.output_per_mtok \
+        + (u.cache_creation_input_tokens - u.cache_creation_1h_input_tokens) * p.cache_write_per_mtok \
+        + u.cache_creation_1h_input_tokens * p.cache_write_1h_per_mtok \
+        + u.cache_read_input_tokens * p.cache_read_per_mtok) / 1000000.0), 0.0) AS cost_usd";
+
+pub async fn record_usage(pool: &SqlitePool, usage: &MessageUsage) -> Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO usage (message_id, chat_id, kind, project_id, model, input_tokens, output_tokens, cache_creation_input_tokens, cache_creation_1h_input_tokens, cache_read_input_tokens, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&usage.message_id)
+    .bind(&usage.chat_id)
+    .bind(&usage.kind)
+    .bind(&usage.project_id)
+    .bind(&usage.model)
+    .bind(usage.input_tokens)
+    .bind(usage.output_tokens)
+    .bind(usage.cache_creation_input_tokens)
+    .bind(usage.cache_creation_1h_input_tokens)
+    .bind(usage.cache_read_input_tokens)
+    .bind(&usage.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_chat_usage(pool: &SqlitePool, chat_id: &str) -> Result<Vec<MessageUsage>> {
+    let usage = sqlx::query_as::<_, MessageUsage>(
+        "SELECT message_id, chat_id, kind, project_id, model, input_tokens, output_tokens, cache_creation_input_tokens, cache_creation_1h_input_tokens, cache_read_input_tokens, created_at FROM usage WHERE chat_id = ?",
+    )
+    .bind(chat_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(usage)
+}
+
+pub async fn chat_usage_totals(pool: &SqlitePool, chat_id: &str) -> Result<UsageTotals> {
+    let totals = sqlx::query_as::<_, UsageTotals>(&format!(
+        "SELECT {} FROM usage u LEFT JOIN model_prices p ON p.model = u.model WHERE u.chat_id = ?",
+        USAGE_TOTALS_COLUMNS
+    ))
+    .bind(chat_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(totals)
+}
+
+pub async fn project_usage_totals(pool: &SqlitePool, project_id: Option<String>) -> Result<UsageTotals> {
+    let totals = sqlx::query_as::<_, UsageTotals>(&format!(
+        "SELECT {} FROM usage u LEFT JOIN model_prices p ON p.model = u.model WHERE u.project_id IS ?",
+        USAGE_TOTALS_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(totals)
+}
+
+/// Totals per UTC day, optionally limited to a project and an inclusive
+/// `YYYY-MM-DD` date range.
+pub async fn daily_usage(
+    pool: &SqlitePool,
+    project_id: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<DailyUsage>> {
+    let days = sqlx::query_as::<_, DailyUsage>(&format!(
+        "SELECT substr(u.created_at, 1, 10) AS day, {} FROM usage u LEFT JOIN model_prices p ON p.model = u.model \
+         WHERE (?1 IS NULL OR u.project_id = ?1) \
+         AND (?2 IS NULL OR substr(u.created_at, 1, 10) >= ?2) \
+         AND (?3 IS NULL OR substr(u.created_at, 1, 10) <= ?3) \
+         GROUP BY day ORDER BY day ASC",
+        USAGE_TOTALS_COLUMNS
+    ))
+    .bind(project_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(days)
+}
+
+// Model price queries
+pub async fn list_model_prices(pool: &SqlitePool) -> Result<Vec<ModelPrice>> {
+    let prices = sqlx::query_as::<_, ModelPrice>(
+        "SELECT model, input_per_mtok, output_per_mtok, cache_write_per_mtok, cache_write_1h_per_mtok, cache_read_per_mtok FROM model_prices ORDER BY model ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(prices)
+}
+
+pub async fn set_model_price(pool: &SqlitePool, price: ModelPrice) -> Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO model_prices (model, input_per_mtok, output_per_mtok, cache_write_per_mtok, cache_write_1h_per_mtok, cache_read_per_mtok) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&price.model)
+    .bind(price.input_per_mtok)
+    .bind(price.output_per_mtok)
+    .bind(price.cache_write_per_mtok)
+    .bind(price.cache_write_1h_per_mtok)
+    .bind(price.cache_read_per_mtok)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_model_price(pool: &SqlitePool, model: &str) -> Result<()> {
+    sqlx::query("DELETE FROM model_prices WHERE model = ?")
+        .bind(model)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// Credential profile queries
+type CredentialProfileRow = (String, String, Option<String>, String, String, String);
+
+const CREDENTIAL_PROFILE_COLUMNS: &str = "id, name, base_url, headers_json, created_at, updated_at";
+
+fn credential_profile((id, name, base_url, headers_json, created_at, updated_at): CredentialProfileRow) -> CredentialProfile {
+    CredentialProfile {
+        id,
+        name,
+        base_url,
+        headers: serde_json::from_str(&headers_json).unwrap_or_default(),
+        created_at,
+        updated_at,
+    }
+}
+
+pub async fn create_credential_profile(
+    pool: &SqlitePool,
+    name: String,
+    base_url: Option<String>,
+    headers: BTreeMap<String, String>,
+) -> Result<CredentialProfile> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+
+    sqlx::query(
+        "INSERT INTO credential_profiles (id, name, base_url, headers_json, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&name)
+    .bind(&base_url)
+    .bind(serde_json::to_string(&headers)?)
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await?;
+
+    Ok(CredentialProfile {
+        id,
+        name,
+        base_url,
+        headers,
+        created_at: now.clone(),
+        updated_at: now,
+    })
+}
+
+pub async fn list_credential_profiles(pool: &SqlitePool) -> Result<Vec<CredentialProfile>> {
+    let rows: Vec<CredentialProfileRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM credential_profiles ORDER BY name COLLATE NOCASE ASC",
+        CREDENTIAL_PROFILE_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(credential_profile).collect())
+}
+
+pub async fn get_credential_profile(pool: &SqlitePool, profile_id: &str) -> Result<CredentialProfile> {
+    let row: CredentialProfileRow = sqlx::query_as(&format!(
+        "SELECT {} FROM credential_profiles WHERE id = ?",
+        CREDENTIAL_PROFILE_COLUMNS
+    ))
+    .bind(profile_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(credential_profile(row))
+}
+
+pub async fn update_credential_profile(pool: &SqlitePool, profile: &CredentialProfile) -> Result<CredentialProfile> {
+    sqlx::query("UPDATE credential_profiles SET name = ?, base_url = ?, headers_json = ?, updated_at = ? WHERE id = ?")
+        .bind(&profile.name)
+        .bind(&profile.base_url)
+        .bind(serde_json::to_string(&profile.headers)?)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&profile.id)
+        .execute(pool)
+        .await?;
+
+    get_credential_profile(pool, &profile.id).await
+}
+
+pub async fn delete_credential_profile(pool: &SqlitePool, profile_id: &str) -> Result<()> {
+    sqlx::query("DELETE FROM credential_profiles WHERE id = ?")
+        .bind(profile_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// Import queries
+
+/// Local id recorded for something imported earlier, if that row still exists.
+pub async fn find_imported(conn: &mut SqliteConnection, source: &str, source_id: &str) -> Result<Option<String>> {
+    let local_id = sqlx::query_scalar(
+        "SELECT i.local_id FROM import_sources i WHERE i.source = ? AND i.source_id = ? AND ( \
+            EXISTS (SELECT 1 FROM projects WHERE id = i.local_id) \
+            OR EXISTS (SELECT 1 FROM chats WHERE id = i.local_id) \
+            OR EXISTS (SELECT 1 FROM messages WHERE id = i.local_id))",
+    )
+    .bind(source)
+    .bind(source_id)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(local_id)
+}
+
+pub async fn record_import(conn: &mut SqliteConnection, source: &str, source_id: &str, local_id: &str) -> Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO import_sources (source, source_id, local_id, imported_at) VALUES (?, ?, ?, ?)",
+    )
+    .bind(source)
+    .bind(source_id)
+    .bind(local_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Like `create_chat`, but keeping the title and creation time from the export.
+pub async fn create_imported_chat(
+    conn: &mut SqliteConnection,
+    project_id: Option<String>,
+    title: String,
+    created_at: String,
+) -> Result<Chat> {
+    let id = uuid::Uuid::new_v4().to_string();
+
+    sqlx::query(
+        "INSERT INTO chats (id, project_id, title, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&project_id)
+    .bind(&title)
+    .bind(&created_at)
+    .bind(&created_at)
+    .execute(conn)
+    .await?;
+
+    Ok(Chat {
+        id,
+        project_id,
+        title,
+        active_leaf_id: None,
+        created_at: created_at.clone(),
+        updated_at: created_at,
+    })
+}
+
+pub async fn set_chat_updated_at(conn: &mut SqliteConnection, chat_id: &str, updated_at: &str) -> Result<()> {
+    sqlx::query("UPDATE chats SET updated_at = ? WHERE id = ?")
+        .bind(updated_at)
+        .bind(chat_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Replace an imported message's text, thinking and attachments with those
+/// from a newer export. Returns `false` when they are unchanged.
+pub async fn update_imported_message(conn: &mut SqliteConnection, message: &Message) -> Result<bool> {
+    let attachment_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM message_attachments WHERE message_id = ?")
+        .bind(&message.id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let updated = sqlx::query(
+        "UPDATE messages SET content = ?, thinking = ? WHERE id = ? AND (content IS NOT ? OR thinking IS NOT ? OR ? <> ?)",
+    )
+    .bind(&message.content)
+    .bind(&message.thinking)
+    .bind(&message.id)
+    .bind(&message.content)
+    .bind(&message.thinking)
+    .bind(attachment_count)
+    .bind(message.attachments.len() as i64)
+    .execute(&mut *conn)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("DELETE FROM message_attachments WHERE message_id = ?")
+        .bind(&message.id)
+        .execute(&mut *conn)
+        .await?;
+    insert_attachments(conn, &message.id, &message.attachments).await?;
+    Ok(true)
+}
@@ -1,6 +1,11 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::FromRow;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Project {
@@ -21,6 +26,85 @@ pub struct ProjectSettings {
     pub max_tokens: u32,
     #[serde(default = "default_temperature")]
     pub temperature: f32,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Offer the built-in tools to the model
+    #[serde(default)]
+    pub tools_enabled: bool,
+    /// MCP servers whose tools are offered in this project's chats
+    #[serde(default)]
+    pub mcp_servers: Vec<McpServerConfig>,
+    #[serde(default)]
+    pub prompt_cache: PromptCache,
+    #[serde(default)]
+    pub context: ContextSettings,
+    /// Id of the credential profile this project's requests use; the default key when unset
+    #[serde(default)]
+    pub credential_profile: Option<String>,
+}
+
+/// Prompt caching of the system prompt, knowledge base and conversation so far.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptCache {
+    #[serde(default = "default_cache_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub ttl: CacheTtl,
+}
+
+/// How long cached prefixes live. Hour-long writes cost more but survive
+/// longer pauses between turns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheTtl {
+    #[default]
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+/// What happens to older turns once a chat outgrows the model's context window.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContextSettings {
+    #[serde(default)]
+    pub strategy: ContextStrategy,
+    /// Context window of the model in tokens, for models other than the 200K ones
+    #[serde(default)]
+    pub window_tokens: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Leave the oldest turns out
+    #[default]
+    Truncate,
+    /// Replace the oldest turns with a summary, written once and stored
+    Summarize,
+}
+
+/// A Model Context Protocol server, spawned and spoken to over stdio.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpServerConfig {
+    /// Prefixes the server's tool names, so it should be short and unique within the project
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+/// How failed or rate-limited requests to the Messages API are retried.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    /// Upper bound for backoff, and for server-requested waits we are willing to honour
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
 }
 
 fn default_model() -> String {
@@ -35,6 +119,41 @@ fn default_temperature() -> f32 {
     1.0
 }
 
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_initial_delay_ms() -> u64 {
+    1000
+}
+
+fn default_max_delay_ms() -> u64 {
+    60_000
+}
+
+fn default_cache_enabled() -> bool {
+    true
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: default_max_retries(),
+            initial_delay_ms: default_initial_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+        }
+    }
+}
+
+impl Default for PromptCache {
+    fn default() -> Self {
+        Self {
+            enabled: default_cache_enabled(),
+            ttl: CacheTtl::default(),
+        }
+    }
+}
+
 impl Default for ProjectSettings {
     fn default() -> Self {
         Self {
@@ -42,6 +161,12 @@ impl Default for ProjectSettings {
             system_prompt: None,
             max_tokens: default_max_tokens(),
             temperature: default_temperature(),
+            retry: RetryPolicy::default(),
+            tools_enabled: false,
+            mcp_servers: Vec::new(),
+            prompt_cache: PromptCache::default(),
+            context: ContextSettings::default(),
+            credential_profile: None,
         }
     }
 }
@@ -51,6 +176,7 @@ pub struct Chat {
     pub id: String,
     pub project_id: Option<String>,
     pub title: String,
+    pub active_leaf_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -59,13 +185,22 @@ pub struct Chat {
 pub struct Message {
     pub id: String,
     pub chat_id: String,
+    pub parent_id: Option<String>,
     pub role: String,
     pub content: String,
-    pub images: Option<String>,
-    pub documents: Option<String>,
     pub model: Option<String>,
     pub extended_thinking: Option<i32>,
+    pub thinking: Option<String>,
+    /// JSON-encoded API turns of a reply that called tools, final answer included
+    pub tool_turns: Option<String>,
+    pub stop_reason: Option<String>,
     pub created_at: String,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub usage: Option<MessageUsage>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub attachments: Vec<MessageAttachment>,
 }
 
 impl Message {
@@ -74,13 +209,17 @@ impl Message {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             chat_id,
+            parent_id: None,
             role: "user".to_string(),
             content,
-            images: None,
-            documents: None,
             model: None,
             extended_thinking: None,
+            thinking: None,
+            tool_turns: None,
+            stop_reason: None,
             created_at: now,
+            usage: None,
+            attachments: Vec::new(),
         }
     }
 
@@ -89,13 +228,155 @@ impl Message {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             chat_id,
+            parent_id: None,
             role: "assistant".to_string(),
             content,
-            images: None,
-            documents: None,
             model: None,
             extended_thinking: None,
+            thinking: None,
+            tool_turns: None,
+            stop_reason: None,
             created_at: now,
+            usage: None,
+            attachments: Vec::new(),
+        }
+    }
+}
+
+/// An image or document attached to a message. The bytes are stored once per
+/// SHA-256 in `attachments`, however many messages reference them.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MessageAttachment {
+    pub sha256: String,
+    /// "image" or "document"
+    pub kind: String,
+    pub media_type: String,
+    /// File name, when there is one
+    pub name: Option<String>,
+    pub size_bytes: i64,
+    /// Base64 content. Only loaded when asked for, and set on new attachments
+    /// until they are stored.
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+impl MessageAttachment {
+    pub fn from_bytes(kind: &str, media_type: String, name: Option<String>, bytes: &[u8]) -> Self {
+        Self {
+            sha256: format!("{:x}", Sha256::digest(bytes)),
+            kind: kind.to_string(),
+            media_type,
+            name,
+            size_bytes: bytes.len() as i64,
+            data: Some(STANDARD.encode(bytes)),
         }
     }
+
+    pub fn from_base64(kind: &str, media_type: String, name: Option<String>, data: &str) -> Result<Self> {
+        let bytes = STANDARD.decode(data.trim()).context("Attachment is not valid base64")?;
+        Ok(Self::from_bytes(kind, media_type, name, &bytes))
+    }
+}
+
+/// A reference file in a project's knowledge base, sent with every chat in
+/// the project. The content is kept in the attachment store.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ProjectDocument {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub sha256: String,
+    pub media_type: String,
+    pub size_bytes: i64,
+    /// Input tokens the document adds to every request; `None` until counted
+    pub token_count: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A named API key, with the endpoint it is sent to and headers to send with
+/// every request. The key itself is kept in the secret store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialProfile {
+    pub id: String,
+    pub name: String,
+    /// Replaces https://api.anthropic.com, e.g. for a gateway or proxy
+    pub base_url: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A summary sent in place of a chat's messages up to and including
+/// `through_message_id`, on every branch that passes through it.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Compaction {
+    pub id: String,
+    pub chat_id: String,
+    pub through_message_id: String,
+    pub summary: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MessageUsage {
+    /// Generated for usage that isn't a reply
+    pub message_id: String,
+    pub chat_id: String,
+    /// "reply", "title" or "summary"
+    pub kind: String,
+    pub project_id: Option<String>,
+    pub model: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_creation_input_tokens: i64,
+    /// Part of `cache_creation_input_tokens` written with the one-hour TTL
+    pub cache_creation_1h_input_tokens: i64,
+    pub cache_read_input_tokens: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UsageTotals {
+    pub message_count: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_creation_input_tokens: i64,
+    pub cache_creation_1h_input_tokens: i64,
+    pub cache_read_input_tokens: i64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DailyUsage {
+    pub day: String,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// A search match. Title matches have no `message_id` or `role`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SearchHit {
+    pub chat_id: String,
+    pub chat_title: String,
+    pub project_id: Option<String>,
+    pub message_id: Option<String>,
+    pub role: Option<String>,
+    /// Matched text, HTML-escaped, with hits wrapped in `<mark>` tags.
+    pub snippet: String,
+    pub created_at: String,
+    pub rank: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ModelPrice {
+    pub model: String,
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+    /// Cache writes with the default five-minute TTL
+    pub cache_write_per_mtok: f64,
+    pub cache_write_1h_per_mtok: f64,
+    pub cache_read_per_mtok: f64,
 }
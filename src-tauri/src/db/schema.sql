@@ -1,35 +0,0 @@
--- Claude Lite Database Schema
-
-CREATE TABLE IF NOT EXISTS projects (
-  id TEXT PRIMARY KEY NOT NULL,
-  name TEXT NOT NULL,
-  settings_json TEXT NOT NULL DEFAULT '{}',
-  created_at TEXT NOT NULL,
-  updated_at TEXT NOT NULL
-);
-
-CREATE TABLE IF NOT EXISTS chats (
-  id TEXT PRIMARY KEY NOT NULL,
-  project_id TEXT REFERENCES projects(id) ON DELETE CASCADE,
-  title TEXT NOT NULL DEFAULT 'New Chat',
-  created_at TEXT NOT NULL,
-  updated_at TEXT NOT NULL
-);
-
-CREATE TABLE IF NOT EXISTS messages (
-  id TEXT PRIMARY KEY NOT NULL,
-  chat_id TEXT NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
-  role TEXT NOT NULL CHECK(role IN ('user', 'assistant')),
-  content TEXT NOT NULL,
-  images TEXT,
-  documents TEXT,
-  model TEXT,
-  extended_thinking INTEGER DEFAULT 0,
-  created_at TEXT NOT NULL
-);
-
--- Indexes for better query performance
-CREATE INDEX IF NOT EXISTS idx_chats_project_id ON chats(project_id);
-CREATE INDEX IF NOT EXISTS idx_messages_chat_id ON messages(chat_id);
-CREATE INDEX IF NOT EXISTS idx_chats_updated_at ON chats(updated_at DESC);
-CREATE INDEX IF NOT EXISTS idx_projects_updated_at ON projects(updated_at DESC);
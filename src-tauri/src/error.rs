@@ -1,37 +1,245 @@
-use serde::Serialize;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
 
-#[derive(Debug, Serialize)]
-pub struct AppError {
+/// Details of an error returned by the Anthropic API.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiError {
     pub message: String,
+    /// Anthropic error `type`, e.g. `rate_limit_error` or `overloaded_error`
+    pub error_type: Option<String>,
+    pub status: Option<u16>,
+    pub request_id: Option<String>,
+    /// How long the server asked us to wait before retrying
+    pub retry_after_ms: Option<u64>,
 }
 
+/// Errors returned by commands. Serialises to an object tagged with a stable
+/// `code` so the UI can tell failures apart without parsing messages.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum AppError {
+    MissingApiKey { message: String },
+    /// A setting from `.env` or the environment is invalid
+    Configuration { message: String },
+    RateLimited(ApiError),
+    Overloaded(ApiError),
+    ContextTooLong(ApiError),
+    Authentication(ApiError),
+    InvalidRequest(ApiError),
+    /// Any other API failure, including 5xx responses
+    Api(ApiError),
+    Network { message: String },
+    Database { message: String },
+    NotFound { message: String },
+    Serialization { message: String },
+    /// A generation is already running for the chat
+    Busy { message: String },
+    InvalidInput { message: String },
+    /// A tool, or the MCP server providing it, failed
+    Tool { message: String },
+    Internal { message: String },
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ErrorBodyDetail,
+}
+
+#[derive(Deserialize)]
+struct ErrorBodyDetail {
+    #[serde(rename = "type")]
+    error_type: String,
+    message: String,
+}
+
+impl AppError {
+    pub fn missing_api_key() -> Self {
+        Self::MissingApiKey {
+            message: "API key not configured. Add one in settings or set the ANTHROPIC_API_KEY environment variable."
+                .to_string(),
+        }
+    }
+
+    /// A setting that was read at startup and must be fixed before the app can use it.
+    pub fn configuration(err: &anyhow::Error) -> Self {
+        Self::Configuration {
+            message: format!("{:#}. Fix the setting in .env or the environment and restart the app.", err),
+        }
+    }
+
+    pub fn busy(message: impl Into<String>) -> Self {
+        Self::Busy {
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            message: message.into(),
+        }
+    }
+
+    pub fn tool(message: impl Into<String>) -> Self {
+        Self::Tool {
+            message: message.into(),
+        }
+    }
+
+    /// Build an API error from a non-2xx response.
+    pub fn from_response(
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        let request_id = headers
+            .get("request-id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let (error_type, message) = match serde_json::from_str::<ErrorBody>(body) {
+            Ok(parsed) => (Some(parsed.error.error_type), parsed.error.message),
+            Err(_) if body.is_empty() => (None, status.to_string()),
+            Err(_) => (None, body.to_string()),
+        };
+
+        Self::from_api(ApiError {
+            message,
+            error_type,
+            status: Some(status.as_u16()),
+            request_id,
+            retry_after_ms: retry_after.map(|d| d.as_millis() as u64),
+        })
+    }
+
+    /// Classify an API error by its Anthropic `type`, falling back to the HTTP status.
+    pub fn from_api(error: ApiError) -> Self {
+        let error_type = error.error_type.as_deref();
+        let status = error.status;
+
+        match (error_type, status) {
+            (Some("rate_limit_error"), _) | (None, Some(429)) => Self::RateLimited(error),
+            (Some("overloaded_error"), _) | (None, Some(529)) => Self::Overloaded(error),
+            (Some("authentication_error"), _) | (None, Some(401)) => Self::Authentication(error),
+            (Some("request_too_large"), _) | (None, Some(413)) => Self::ContextTooLong(error),
+            (Some("invalid_request_error"), _) if is_context_overflow(&error.message) => {
+                Self::ContextTooLong(error)
+            }
+            (Some("invalid_request_error"), _) | (None, Some(400)) => Self::InvalidRequest(error),
+            _ => Self::Api(error),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::RateLimited(e)
+            | Self::Overloaded(e)
+            | Self::ContextTooLong(e)
+            | Self::Authentication(e)
+            | Self::InvalidRequest(e)
+            | Self::Api(e) => &e.message,
+            Self::MissingApiKey { message }
+            | Self::Configuration { message }
+            | Self::Network { message }
+            | Self::Database { message }
+            | Self::NotFound { message }
+            | Self::Serialization { message }
+            | Self::Busy { message }
+            | Self::InvalidInput { message }
+            | Self::Tool { message }
+            | Self::Internal { message } => message,
+        }
+    }
+
+    /// Whether repeating the same request may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimited(_) | Self::Overloaded(_) | Self::Network { .. } => true,
+            Self::Api(e) => e.status.is_none_or(|s| s >= 500),
+            _ => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited(e) | Self::Overloaded(e) | Self::Api(e) => {
+                e.retry_after_ms.map(Duration::from_millis)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_context_overflow(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("prompt is too long")
+        || message.contains("context window")
+        || message.contains("too many tokens")
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
 impl From<anyhow::Error> for AppError {
     fn from(err: anyhow::Error) -> Self {
-        Self {
-            message: err.to_string(),
+        // The db module wraps sqlx and serde errors in anyhow; keep their codes
+        let message = format!("{:#}", err);
+        if let Some(sqlx_err) = err.downcast_ref::<sqlx::Error>() {
+            return match sqlx_err {
+                sqlx::Error::RowNotFound => Self::NotFound { message },
+                _ => Self::Database { message },
+            };
         }
+        if err.downcast_ref::<serde_json::Error>().is_some() {
+            return Self::Serialization { message };
+        }
+        if let Some(app_err) = err.downcast_ref::<AppError>() {
+            return app_err.clone();
+        }
+
+        Self::Internal { message }
     }
 }
 
 impl From<sqlx::Error> for AppError {
     fn from(err: sqlx::Error) -> Self {
-        Self {
-            message: format!("Database error: {}", err),
+        match err {
+            sqlx::Error::RowNotFound => Self::NotFound {
+                message: "Not found".to_string(),
+            },
+            err => Self::Database {
+                message: format!("Database error: {}", err),
+            },
         }
     }
 }
 
 impl From<reqwest::Error> for AppError {
     fn from(err: reqwest::Error) -> Self {
-        Self {
-            message: format!("HTTP error: {}", err),
+        // Builder errors mean we constructed a bad request; everything else is transport
+        if err.is_builder() {
+            Self::Internal {
+                message: format!("HTTP error: {}", err),
+            }
+        } else {
+            Self::Network {
+                message: format!("HTTP error: {}", err),
+            }
         }
     }
 }
 
 impl From<serde_json::Error> for AppError {
     fn from(err: serde_json::Error) -> Self {
-        Self {
+        Self::Serialization {
             message: format!("JSON error: {}", err),
         }
     }
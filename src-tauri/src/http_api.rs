@@ -0,0 +1,324 @@
+use crate::commands::anthropic::{EventSink, GenerationEvent};
+use crate::commands::{post_message, MessageDocument, MessageImage, NewMessage};
+use crate::db;
+use crate::db::models::{Chat, Message, Project};
+use crate::error::{AppError, Result};
+use crate::state::AppState;
+use anyhow::Context;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::env;
+use std::io::Write;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::mpsc;
+
+const PORT_VAR: &str = "CLAUDE_LITE_HTTP_PORT";
+const TOKEN_VAR: &str = "CLAUDE_LITE_HTTP_TOKEN";
+const TOKEN_FILE: &str = "http-api-token";
+
+/// Settings for the local HTTP API. The server only listens on 127.0.0.1.
+#[derive(Debug, Clone)]
+pub struct HttpApiConfig {
+    pub port: u16,
+    /// Clients must send `Authorization: Bearer <token>`
+    pub token: String,
+}
+
+impl HttpApiConfig {
+    /// The API is off unless `CLAUDE_LITE_HTTP_PORT` is set. Without
+    /// `CLAUDE_LITE_HTTP_TOKEN`, a random token is generated once and kept in
+    /// `http-api-token` in the app data directory.
+    pub fn from_env(app_dir: &std::path::Path) -> anyhow::Result<Option<Self>> {
+        let Ok(port) = env::var(PORT_VAR) else {
+            return Ok(None);
+        };
+        let port = port
+            .trim()
+            .parse()
+            .with_context(|| format!("{} must be a port number", PORT_VAR))?;
+
+        let token = match env::var(TOKEN_VAR) {
+            Ok(token) if !token.trim().is_empty() => token.trim().to_string(),
+            _ => stored_token(&app_dir.join(TOKEN_FILE))?,
+        };
+
+        Ok(Some(Self { port, token }))
+    }
+}
+
+fn stored_token(path: &std::path::Path) -> anyhow::Result<String> {
+    if let Ok(token) = std::fs::read_to_string(path) {
+        if !token.trim().is_empty() {
+            return Ok(token.trim().to_string());
+        }
+    }
+
+    let token = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .with_context(|| format!("Failed to write HTTP API token to {:?}", path))?;
+
+    println!("HTTP API token written to {:?}", path);
+    Ok(token)
+}
+
+#[derive(Clone)]
+struct ApiState {
+    app: AppState,
+    token: String,
+}
+
+pub async fn serve(app: AppState, config: HttpApiConfig) -> anyhow::Result<()> {
+    let state = ApiState {
+        app,
+        token: config.token,
+    };
+
+    let router = Router::new()
+        .route("/v1/projects", get(list_projects).post(create_project))
+        .route("/v1/projects/:project_id", get(get_project))
+        .route("/v1/chats", get(list_chats).post(create_chat))
+        .route("/v1/chats/:chat_id", get(get_chat).delete(delete_chat))
+        .route("/v1/chats/:chat_id/messages", get(list_messages).post(send_message))
+        .route("/v1/attachments/:sha256", get(get_attachment))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state);
+
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, config.port));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    println!("HTTP API listening on http://{}", addr);
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+async fn require_token(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    if !constant_time_eq(presented.as_bytes(), state.token.as_bytes()) {
+        return (StatusCode::UNAUTHORIZED, "Missing or invalid API token").into_response();
+    }
+    next.run(request).await
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            AppError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AppError::InvalidInput { .. } | AppError::InvalidRequest(_) | AppError::ContextTooLong(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            AppError::Busy { .. } => StatusCode::CONFLICT,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::MissingApiKey { .. } | AppError::Configuration { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Authentication(_)
+            | AppError::Overloaded(_)
+            | AppError::Api(_)
+            | AppError::Network { .. }
+            | AppError::Tool { .. } => StatusCode::BAD_GATEWAY,
+            AppError::Database { .. } | AppError::Serialization { .. } | AppError::Internal { .. } => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+// Projects
+
+async fn list_projects(State(state): State<ApiState>) -> Result<Json<Vec<Project>>> {
+    Ok(Json(db::list_projects(&state.app.db).await?))
+}
+
+#[derive(Deserialize)]
+struct CreateProject {
+    name: String,
+}
+
+async fn create_project(State(state): State<ApiState>, Json(body): Json<CreateProject>) -> Result<Json<Project>> {
+    Ok(Json(db::create_project(&state.app.db, body.name).await?))
+}
+
+async fn get_project(State(state): State<ApiState>, Path(project_id): Path<String>) -> Result<Json<Project>> {
+    Ok(Json(db::get_project(&state.app.db, &project_id).await?))
+}
+
+// Chats
+
+#[derive(Deserialize)]
+struct ChatFilter {
+    #[serde(default)]
+    project_id: Option<String>,
+}
+
+async fn list_chats(State(state): State<ApiState>, Query(filter): Query<ChatFilter>) -> Result<Json<Vec<Chat>>> {
+    Ok(Json(db::list_chats(&state.app.db, filter.project_id).await?))
+}
+
+async fn create_chat(State(state): State<ApiState>, Json(body): Json<ChatFilter>) -> Result<Json<Chat>> {
+    Ok(Json(db::create_chat(&state.app.db, body.project_id).await?))
+}
+
+async fn get_chat(State(state): State<ApiState>, Path(chat_id): Path<String>) -> Result<Json<Chat>> {
+    Ok(Json(db::get_chat(&state.app.db, &chat_id).await?))
+}
+
+async fn delete_chat(State(state): State<ApiState>, Path(chat_id): Path<String>) -> Result<StatusCode> {
+    db::delete_chat(&state.app.db, &chat_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Messages
+
+async fn list_messages(State(state): State<ApiState>, Path(chat_id): Path<String>) -> Result<Json<Vec<Message>>> {
+    Ok(Json(db::list_messages(&state.app.db, &chat_id).await?))
+}
+
+// Attachments
+
+/// Raw attachment content, served with its media type.
+async fn get_attachment(State(state): State<ApiState>, Path(sha256): Path<String>) -> Result<Response> {
+    let (media_type, data) = db::get_attachment(&state.app.db, &sha256).await?;
+    Ok(([(header::CONTENT_TYPE, media_type)], data).into_response())
+}
+
+/// A message sent over the HTTP API. Attachments come inline as base64:
+/// unlike the app and the CLI, clients can't have files read from disk.
+#[derive(Deserialize)]
+struct PostedMessage {
+    content: String,
+    #[serde(default)]
+    project_id: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    images: Option<Vec<MessageImage>>,
+    #[serde(default)]
+    documents: Option<Vec<MessageDocument>>,
+    #[serde(default)]
+    extended_thinking: bool,
+}
+
+impl From<PostedMessage> for NewMessage {
+    fn from(message: PostedMessage) -> Self {
+        Self {
+            content: message.content,
+            project_id: message.project_id,
+            model: message.model,
+            images: message.images,
+            documents: message.documents,
+            file_paths: None,
+            extended_thinking: message.extended_thinking,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SendOptions {
+    /// Set to false to wait for the reply and get it back as plain JSON
+    #[serde(default = "default_stream")]
+    stream: bool,
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+/// Forwards generation events to an SSE response. The receiver going away
+/// doesn't stop the generation; the reply is still saved.
+struct SseSink {
+    events: mpsc::UnboundedSender<Event>,
+    errored: AtomicBool,
+}
+
+impl SseSink {
+    fn send<T: serde::Serialize>(&self, name: &str, data: &T) {
+        if let Ok(event) = Event::default().event(name).json_data(data) {
+            let _ = self.events.send(event);
+        }
+    }
+}
+
+impl EventSink for SseSink {
+    fn emit(&self, event: GenerationEvent) {
+        if let GenerationEvent::Error { .. } = event {
+            self.errored.store(true, Ordering::SeqCst);
+        }
+        let Ok(data) = serde_json::to_value(&event) else {
+            return;
+        };
+        let name = data["type"].as_str().unwrap_or("event").to_string();
+        self.send(&name, &data);
+    }
+}
+
+/// No-op sink for non-streaming requests.
+struct Discard;
+
+impl EventSink for Discard {
+    fn emit(&self, _event: GenerationEvent) {}
+}
+
+/// Post a user message and stream the reply as server-sent events. Each
+/// generation event is sent under its `type` as the event name; the saved
+/// assistant message follows as a final `message` event.
+async fn send_message(
+    State(state): State<ApiState>,
+    Path(chat_id): Path<String>,
+    Query(options): Query<SendOptions>,
+    Json(message): Json<PostedMessage>,
+) -> Result<Response> {
+    let message = NewMessage::from(message);
+    if !options.stream {
+        let reply = post_message(&state.app, &Discard, &chat_id, message).await?;
+        return Ok(Json(reply).into_response());
+    }
+
+    // Fail fast with a plain error response before committing to a stream
+    db::get_chat(&state.app.db, &chat_id).await?;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let sink = SseSink {
+            events: tx,
+            errored: AtomicBool::new(false),
+        };
+        match post_message(&state.app, &sink, &chat_id, message).await {
+            Ok(reply) => sink.send("message", &reply),
+            Err(error) if !sink.errored.load(Ordering::SeqCst) => sink.send(
+                "error",
+                &GenerationEvent::Error { error },
+            ),
+            Err(_) => {}
+        }
+    });
+
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (Ok::<_, Infallible>(event), rx))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()).into_response())
+}
@@ -0,0 +1,117 @@
+use super::{join_text, timestamp, title, Conversation, ImportedMessage};
+use serde_json::Value;
+
+/// Read a conversation from a ChatGPT `conversations.json`. Messages are a
+/// tree of nodes in `mapping`; uploaded images sit next to it in the zip as
+/// `file-<id>-<name>`.
+pub(super) fn conversation(value: &Value, files: &[String]) -> Option<Conversation> {
+    let mapping = value["mapping"].as_object()?;
+    let source_id = value["conversation_id"].as_str().or(value["id"].as_str())?;
+    let created_at = timestamp(&value["create_time"])?;
+    let updated_at = timestamp(&value["update_time"]).unwrap_or_else(|| created_at.clone());
+
+    // Only the branch that was on screen is imported
+    let mut path = Vec::new();
+    let mut node_id = value["current_node"].as_str();
+    while let Some(id) = node_id {
+        let Some(node) = mapping.get(id) else {
+            break;
+        };
+        if path.len() > mapping.len() {
+            break;
+        }
+        path.push(node);
+        node_id = node["parent"].as_str();
+    }
+    path.reverse();
+
+    let mut sent_at = created_at.clone();
+    let messages: Vec<ImportedMessage> = path
+        .iter()
+        .filter_map(|node| {
+            let message = message(&node["message"], files, &sent_at)?;
+            sent_at = message.created_at.clone();
+            Some(message)
+        })
+        .collect();
+    if messages.is_empty() {
+        return None;
+    }
+
+    Some(Conversation {
+        source_id: source_id.to_string(),
+        title: title(&value["title"]),
+        created_at,
+        updated_at,
+        messages,
+    })
+}
+
+fn message(value: &Value, files: &[String], previous_time: &str) -> Option<ImportedMessage> {
+    let role = match value["author"]["role"].as_str()? {
+        "user" => "user",
+        "assistant" => "assistant",
+        _ => return None,
+    };
+    // System context, custom instructions and calls addressed to tools rather than the user
+    if value["metadata"]["is_visually_hidden_from_conversation"] == true
+        || value["recipient"].as_str().is_some_and(|recipient| recipient != "all")
+    {
+        return None;
+    }
+
+    let content = &value["content"];
+    let mut text = Vec::new();
+    let mut thinking = Vec::new();
+    let mut images = Vec::new();
+    match content["content_type"].as_str()? {
+        "text" | "multimodal_text" => {
+            for part in content["parts"].as_array().into_iter().flatten() {
+                if let Some(part) = part.as_str() {
+                    text.push(part.to_string());
+                    continue;
+                }
+                match part["content_type"].as_str() {
+                    Some("image_asset_pointer") => {
+                        images.extend(part["asset_pointer"].as_str().and_then(|p| asset_file(p, files)))
+                    }
+                    Some("audio_transcription") => text.extend(part["text"].as_str().map(str::to_string)),
+                    _ => {}
+                }
+            }
+        }
+        "thoughts" => {
+            for thought in content["thoughts"].as_array().into_iter().flatten() {
+                thinking.extend(thought["content"].as_str().map(str::to_string));
+            }
+        }
+        _ => return None,
+    }
+
+    let text = join_text(&text);
+    if text.is_empty() && thinking.is_empty() && images.is_empty() {
+        return None;
+    }
+
+    Some(ImportedMessage {
+        source_ids: vec![value["id"].as_str()?.to_string()],
+        role,
+        content: text,
+        thinking,
+        model: value["metadata"]["model_slug"]
+            .as_str()
+            .filter(|_| role == "assistant")
+            .map(str::to_string),
+        images,
+        created_at: timestamp(&value["create_time"]).unwrap_or_else(|| previous_time.to_string()),
+    })
+}
+
+/// Archive entry for an asset pointer such as `file-service://file-abc123`.
+fn asset_file(pointer: &str, files: &[String]) -> Option<String> {
+    let file_id = pointer.rsplit("://").next()?;
+    files
+        .iter()
+        .find(|name| name.rsplit('/').next().unwrap_or(name).starts_with(file_id))
+        .cloned()
+}
@@ -0,0 +1,80 @@
+use super::{join_text, timestamp, title, Conversation, ImportedMessage};
+use serde_json::Value;
+
+/// Read a conversation from a claude.ai `conversations.json`, where
+/// `chat_messages` are listed in order.
+pub(super) fn conversation(value: &Value) -> Option<Conversation> {
+    let created_at = timestamp(&value["created_at"])?;
+    let updated_at = timestamp(&value["updated_at"]).unwrap_or_else(|| created_at.clone());
+
+    let mut sent_at = created_at.clone();
+    let messages: Vec<ImportedMessage> = value["chat_messages"]
+        .as_array()?
+        .iter()
+        .filter_map(|m| {
+            let message = message(m, &sent_at)?;
+            sent_at = message.created_at.clone();
+            Some(message)
+        })
+        .collect();
+    if messages.is_empty() {
+        return None;
+    }
+
+    Some(Conversation {
+        source_id: value["uuid"].as_str()?.to_string(),
+        title: title(&value["name"]),
+        created_at,
+        updated_at,
+        messages,
+    })
+}
+
+fn message(value: &Value, previous_time: &str) -> Option<ImportedMessage> {
+    let role = match value["sender"].as_str()? {
+        "human" => "user",
+        "assistant" => "assistant",
+        _ => return None,
+    };
+
+    let mut text = Vec::new();
+    let mut thinking = Vec::new();
+    match value["content"].as_array() {
+        Some(blocks) if !blocks.is_empty() => {
+            for block in blocks {
+                match block["type"].as_str() {
+                    Some("text") => text.extend(block["text"].as_str().map(str::to_string)),
+                    Some("thinking") => thinking.extend(block["thinking"].as_str().map(str::to_string)),
+                    _ => {}
+                }
+            }
+        }
+        _ => text.extend(value["text"].as_str().map(str::to_string)),
+    }
+
+    // Only the text extracted from attached files is in the export
+    for attachment in value["attachments"].as_array().into_iter().flatten() {
+        let Some(extracted) = attachment["extracted_content"].as_str() else {
+            continue;
+        };
+        if !extracted.trim().is_empty() {
+            let name = attachment["file_name"].as_str().unwrap_or("attachment");
+            text.push(format!("<document name=\"{}\">\n{}\n</document>", name, extracted.trim_end()));
+        }
+    }
+
+    let content = join_text(&text);
+    if content.is_empty() && thinking.is_empty() {
+        return None;
+    }
+
+    Some(ImportedMessage {
+        source_ids: vec![value["uuid"].as_str()?.to_string()],
+        role,
+        content,
+        thinking,
+        model: None,
+        images: Vec::new(),
+        created_at: timestamp(&value["created_at"]).unwrap_or_else(|| previous_time.to_string()),
+    })
+}
@@ -0,0 +1,375 @@
+mod chatgpt;
+mod claude;
+
+use crate::db;
+use crate::db::models::{Message, MessageAttachment};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Larger images are left out; the API would reject them anyway
+const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    ClaudeAi,
+    Chatgpt,
+}
+
+impl Source {
+    /// Namespace for ids in `import_sources`
+    fn key(self) -> &'static str {
+        match self {
+            Source::ClaudeAi => "claude.ai",
+            Source::Chatgpt => "chatgpt",
+        }
+    }
+
+    /// Project that chats go into when no other is chosen
+    fn project_name(self) -> &'static str {
+        match self {
+            Source::ClaudeAi => "claude.ai",
+            Source::Chatgpt => "ChatGPT",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub source: Source,
+    pub project_id: String,
+    pub chats_created: usize,
+    /// Chats imported before that gained messages
+    pub chats_updated: usize,
+    pub messages_imported: usize,
+}
+
+/// A conversation read from an export, messages in the order they were sent.
+struct Conversation {
+    source_id: String,
+    title: String,
+    created_at: String,
+    updated_at: String,
+    messages: Vec<ImportedMessage>,
+}
+
+struct ImportedMessage {
+    /// More than one when consecutive messages were merged into this one
+    source_ids: Vec<String>,
+    role: &'static str,
+    content: String,
+    thinking: Vec<String>,
+    model: Option<String>,
+    /// Names of image files inside the export archive
+    images: Vec<String>,
+    created_at: String,
+}
+
+struct Export {
+    source: Source,
+    conversations: Vec<Conversation>,
+    archive: Option<ZipArchive<File>>,
+}
+
+/// Import a claude.ai or ChatGPT export: either its `conversations.json` or
+/// the whole zip. Chats go into `project_id`, or a project named after the
+/// source. Conversations and messages imported before are skipped, so a
+/// newer export of the same account only adds what is new.
+pub async fn import_file(pool: &SqlitePool, path: PathBuf, project_id: Option<String>) -> Result<ImportSummary> {
+    let mut export = tokio::task::spawn_blocking(move || read_export(&path)).await??;
+
+    let project_id = match project_id {
+        Some(project_id) => db::get_project(pool, &project_id).await?.id,
+        None => source_project(pool, export.source).await?,
+    };
+
+    let mut summary = ImportSummary {
+        source: export.source,
+        project_id,
+        chats_created: 0,
+        chats_updated: 0,
+        messages_imported: 0,
+    };
+    for conversation in std::mem::take(&mut export.conversations) {
+        import_conversation(pool, &mut export, conversation, &mut summary).await?;
+    }
+
+    Ok(summary)
+}
+
+fn read_export(path: &Path) -> Result<Export> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut magic = [0u8; 4];
+    let is_zip = file.read(&mut magic)? == magic.len() && &magic == b"PK\x03\x04";
+    file.rewind()?;
+
+    let mut json = Vec::new();
+    let mut files = Vec::new();
+    let archive = if is_zip {
+        let mut archive = ZipArchive::new(file).context("Failed to read the export archive")?;
+        files = archive.file_names().map(str::to_string).collect();
+        let name = files
+            .iter()
+            .filter(|name| *name == "conversations.json" || name.ends_with("/conversations.json"))
+            .min_by_key(|name| name.len())
+            .context("No conversations.json in the export archive")?;
+        archive.by_name(name)?.read_to_end(&mut json)?;
+        Some(archive)
+    } else {
+        file.read_to_end(&mut json)?;
+        None
+    };
+
+    let conversations: Vec<Value> =
+        serde_json::from_slice(&json).context("conversations.json is not a list of conversations")?;
+    let (source, conversations) = match conversations.first() {
+        Some(first) if first.get("mapping").is_some() => (
+            Source::Chatgpt,
+            conversations
+                .iter()
+                .filter_map(|c| chatgpt::conversation(c, &files))
+                .collect(),
+        ),
+        Some(first) if first.get("chat_messages").is_some() => (
+            Source::ClaudeAi,
+            conversations.iter().filter_map(claude::conversation).collect(),
+        ),
+        Some(_) => bail!("Not a claude.ai or ChatGPT export"),
+        None => bail!("The export has no conversations"),
+    };
+
+    Ok(Export {
+        source,
+        conversations,
+        archive,
+    })
+}
+
+async fn source_project(pool: &SqlitePool, source: Source) -> Result<String> {
+    let mut conn = pool.acquire().await?;
+    if let Some(project_id) = db::find_imported(&mut conn, source.key(), "project").await? {
+        return Ok(project_id);
+    }
+
+    let project = db::create_project(pool, source.project_name().to_string()).await?;
+    db::record_import(&mut conn, source.key(), "project", &project.id).await?;
+    Ok(project.id)
+}
+
+async fn import_conversation(
+    pool: &SqlitePool,
+    export: &mut Export,
+    conversation: Conversation,
+    summary: &mut ImportSummary,
+) -> Result<()> {
+    // A conversation is imported whole or not at all
+    let mut tx = pool.begin().await?;
+    let source = export.source.key();
+    let chat_key = format!("conversation:{}", conversation.source_id);
+    let (chat_id, is_new) = match db::find_imported(&mut tx, source, &chat_key).await? {
+        Some(chat_id) => (chat_id, false),
+        None => {
+            let chat = db::create_imported_chat(
+                &mut tx,
+                Some(summary.project_id.clone()),
+                conversation.title,
+                conversation.created_at,
+            )
+            .await?;
+            db::record_import(&mut tx, source, &chat_key, &chat.id).await?;
+            (chat.id, true)
+        }
+    };
+
+    // Known messages are walked past, so new ones attach where the conversation continued
+    let mut parent_id = None;
+    let mut added = 0;
+    let mut turns = merge_turns(conversation.messages);
+    // The API wants the user to speak first, so a conversation the assistant
+    // opened gets a stand-in user turn, with an id of its own so re-imports
+    // find it
+    if let Some(first) = turns.first().filter(|first| first.role == "assistant") {
+        let opening = ImportedMessage {
+            source_ids: vec![format!("{}:opening", conversation.source_id)],
+            role: "user",
+            content: "(The conversation was started by the assistant.)".to_string(),
+            thinking: Vec::new(),
+            model: None,
+            images: Vec::new(),
+            created_at: first.created_at.clone(),
+        };
+        turns.insert(0, opening);
+    }
+    for imported in turns {
+        let message_keys: Vec<String> = imported.source_ids.iter().map(|id| format!("message:{}", id)).collect();
+        let mut known = None;
+        let mut unknown_keys = Vec::new();
+        for key in &message_keys {
+            match db::find_imported(&mut tx, source, key).await? {
+                Some(message_id) => known = known.or(Some(message_id)),
+                None => unknown_keys.push(key),
+            }
+        }
+        if unknown_keys.is_empty() {
+            parent_id = known;
+            continue;
+        }
+
+        let mut message = match imported.role {
+            "assistant" => Message::new_assistant(chat_id.clone(), imported.content),
+            _ => Message::new_user(chat_id.clone(), imported.content),
+        };
+        message.parent_id = parent_id.take();
+        message.model = imported.model;
+        message.created_at = imported.created_at;
+        message.thinking = thinking_json(&imported.thinking);
+        // The API only accepts images from the user
+        if imported.role == "user" {
+            message.attachments = load_images(&mut export.archive, imported.images).await?;
+        }
+
+        // Messages merged into one imported before extend it
+        let message_id = match known {
+            Some(message_id) => {
+                message.id = message_id;
+                if db::update_imported_message(&mut tx, &message).await? {
+                    added += 1;
+                }
+                message.id
+            }
+            None => {
+                db::insert_message(&mut tx, &message).await?;
+                added += 1;
+                message.id
+            }
+        };
+        for key in unknown_keys {
+            db::record_import(&mut tx, source, key, &message_id).await?;
+        }
+        parent_id = Some(message_id);
+    }
+
+    if added > 0 {
+        db::set_chat_updated_at(&mut tx, &chat_id, &conversation.updated_at).await?;
+        summary.messages_imported += added;
+        if !is_new {
+            summary.chats_updated += 1;
+        }
+    }
+    if is_new {
+        summary.chats_created += 1;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The API expects user and assistant turns to alternate, so consecutive
+/// messages from the same side are joined, keeping all their ids.
+fn merge_turns(messages: Vec<ImportedMessage>) -> Vec<ImportedMessage> {
+    let mut merged: Vec<ImportedMessage> = Vec::new();
+    for message in messages {
+        match merged.last_mut() {
+            Some(last) if last.role == message.role => {
+                if !message.content.is_empty() {
+                    if !last.content.is_empty() {
+                        last.content.push_str("\n\n");
+                    }
+                    last.content.push_str(&message.content);
+                }
+                last.source_ids.extend(message.source_ids);
+                last.thinking.extend(message.thinking);
+                last.images.extend(message.images);
+                last.model = last.model.take().or(message.model);
+            }
+            _ => merged.push(message),
+        }
+    }
+    merged
+}
+
+/// Stored like streamed thinking, but unsigned, so it is shown and never sent back.
+fn thinking_json(thinking: &[String]) -> Option<String> {
+    if thinking.is_empty() {
+        return None;
+    }
+    let blocks: Vec<Value> = thinking
+        .iter()
+        .map(|text| json!({ "type": "thinking", "thinking": text, "signature": "" }))
+        .collect();
+    serde_json::to_string(&blocks).ok()
+}
+
+/// Read images out of the archive off the async runtime. Ones that can't be
+/// read are left out.
+async fn load_images(archive: &mut Option<ZipArchive<File>>, names: Vec<String>) -> Result<Vec<MessageAttachment>> {
+    if names.is_empty() || archive.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let mut taken = archive.take();
+    let (taken, images) = tokio::task::spawn_blocking(move || {
+        let images = names.iter().filter_map(|name| load_image(&mut taken, name)).collect();
+        (taken, images)
+    })
+    .await?;
+    *archive = taken;
+    Ok(images)
+}
+
+fn load_image(archive: &mut Option<ZipArchive<File>>, name: &str) -> Option<MessageAttachment> {
+    let extension = name.rsplit('.').next()?.to_ascii_lowercase();
+    let media_type = match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => return None,
+    };
+
+    let mut file = archive.as_mut()?.by_name(name).ok()?;
+    if file.size() > MAX_IMAGE_BYTES {
+        return None;
+    }
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).ok()?;
+
+    Some(MessageAttachment::from_bytes("image", media_type.to_string(), None, &data))
+}
+
+/// RFC 3339 string or Unix seconds, normalised to how the app stores times.
+fn timestamp(value: &Value) -> Option<String> {
+    let time = match value {
+        Value::String(text) => DateTime::parse_from_rfc3339(text).ok()?.with_timezone(&Utc),
+        Value::Number(seconds) => {
+            let seconds = seconds.as_f64()?;
+            DateTime::from_timestamp(seconds.trunc() as i64, (seconds.fract() * 1e9) as u32)?
+        }
+        _ => return None,
+    };
+    Some(time.to_rfc3339())
+}
+
+fn title(value: &Value) -> String {
+    value
+        .as_str()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .unwrap_or("Untitled")
+        .to_string()
+}
+
+fn join_text(parts: &[String]) -> String {
+    parts
+        .iter()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
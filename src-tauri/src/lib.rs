@@ -1,11 +1,19 @@
+mod api_config;
+mod attachments;
+pub mod cli;
 mod commands;
+mod context;
 mod db;
 mod error;
+mod http_api;
+mod import;
+mod markdown;
+mod secrets;
 mod state;
+mod tools;
 
 use state::AppState;
-use std::env;
-use tauri::Manager;
+use tauri::{Manager, RunEvent};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -15,47 +23,145 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
-            // Get API key from environment
-            let api_key = env::var("ANTHROPIC_API_KEY").ok();
-
-            if api_key.is_none() {
-                eprintln!("Warning: ANTHROPIC_API_KEY not set in environment");
-            }
-
             // Initialize database
             let app_handle = app.handle().clone();
             let app_dir = app.path().app_data_dir().expect("Failed to get app data directory");
+
+            let secret_store = secrets::SecretStore::new(app_dir.clone());
             let db_path = app_dir.join("claude.db");
 
             println!("App data directory: {:?}", app_dir);
             println!("Database path: {:?}", db_path);
 
+            // Created up front: the HTTP API token is written there before the database
+            if let Err(e) = std::fs::create_dir_all(&app_dir) {
+                eprintln!("Failed to create app data directory {:?}: {}", app_dir, e);
+            }
+
+            // Kept as an error for the backup commands to report
+            let backups = db::backup::BackupConfig::from_env(db_path.clone()).map_err(|e| {
+                eprintln!("Invalid backup settings, backups are off until fixed: {:#}", e);
+                error::AppError::configuration(&e.context("Invalid backup settings"))
+            });
+
+            // Kept as an error for every request to report, rather than sent elsewhere
+            let api = api_config::ApiConfig::from_env().map_err(|e| {
+                eprintln!("Invalid API settings, requests will fail until fixed: {:#}", e);
+                error::AppError::configuration(&e.context("Invalid API settings"))
+            });
+
+            let http_api = http_api::HttpApiConfig::from_env(&app_dir)
+                .unwrap_or_else(|e| {
+                    eprintln!("HTTP API disabled: {:#}", e);
+                    None
+                });
+
             // Initialize DB and state
             tauri::async_runtime::block_on(async move {
                 let pool = db::init_db(db_path.clone()).await
                     .unwrap_or_else(|e| panic!("Failed to initialize database at {:?}: {}", db_path, e));
-                let state = AppState::new(pool, api_key);
+
+                // A key saved in the app wins over ANTHROPIC_API_KEY from the environment
+                let store = secret_store.clone();
+                let api_key = tauri::async_runtime::spawn_blocking(move || secrets::load_api_key(&store))
+                    .await
+                    .unwrap_or_default();
+                if api_key.is_none() {
+                    eprintln!("Warning: no API key saved and ANTHROPIC_API_KEY not set in environment");
+                }
+                let state = AppState::new(pool, api, secret_store, api_key);
+
+                if let Ok(backups) = &backups {
+                    tauri::async_runtime::spawn(db::backup::run_schedule(state.db.clone(), backups.clone()));
+                }
+
+                // Optional local API for scripts and editor plugins
+                if let Some(config) = http_api {
+                    let state = state.clone();
+                    tauri::async_runtime::spawn(async move {
+                        if let Err(e) = http_api::serve(state, config).await {
+                            eprintln!("HTTP API stopped: {:#}", e);
+                        }
+                    });
+                }
+
                 app_handle.manage(state);
+                app_handle.manage(backups);
             });
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
+            commands::get_api_key_status,
+            commands::set_api_key,
+            commands::clear_api_key,
+            commands::validate_api_key,
+            commands::list_credential_profiles,
+            commands::create_credential_profile,
+            commands::update_credential_profile,
+            commands::delete_credential_profile,
+            commands::validate_credential_profile,
             commands::list_projects,
             commands::create_project,
             commands::get_project,
             commands::get_project_settings,
             commands::update_project_settings,
             commands::delete_project,
+            commands::add_project_document,
+            commands::list_project_documents,
+            commands::update_project_document,
+            commands::remove_project_document,
             commands::list_chats,
             commands::create_chat,
             commands::get_chat,
             commands::update_chat_title,
             commands::delete_chat,
             commands::list_messages,
+            commands::get_attachment,
+            commands::list_compactions,
             commands::send_message,
+            commands::edit_message,
+            commands::regenerate_message,
+            commands::list_branches,
+            commands::switch_branch,
+            commands::cancel_generation,
             commands::generate_title,
+            commands::search_messages,
+            commands::get_chat_usage,
+            commands::get_project_usage,
+            commands::get_daily_usage,
+            commands::list_model_prices,
+            commands::set_model_price,
+            commands::delete_model_price,
+            commands::list_project_tools,
+            commands::export_chat,
+            commands::export_project,
+            commands::import_conversations,
+            commands::create_backup,
+            commands::list_backups,
+            commands::restore_backup,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            match event {
+                // Stop in-flight generations so their partial replies get saved before exiting
+                RunEvent::ExitRequested { api, .. } => {
+                    let state = app_handle.state::<AppState>();
+                    if state.has_generations() {
+                        api.prevent_exit();
+
+                        let app_handle = app_handle.clone();
+                        let state = state.inner().clone();
+                        tauri::async_runtime::spawn(async move {
+                            state.stop_all_generations().await;
+                            app_handle.exit(0);
+                        });
+                    }
+                }
+                // Don't leave MCP server processes behind
+                RunEvent::Exit => app_handle.state::<AppState>().mcp.shutdown(),
+                _ => {}
+            }
+        });
 }
@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use keyring::Entry;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Service secrets are filed under in the OS keyring
+const SERVICE: &str = "claude-lite";
+/// The Anthropic API key used when nothing else is configured
+pub const API_KEY: &str = "anthropic-api-key";
+
+const SECRETS_FILE: &str = "secrets.enc";
+const KEY_FILE: &str = "secrets.key";
+const NONCE_LEN: usize = 12;
+
+/// Where a secret ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretBackend {
+    Keyring,
+    EncryptedFile,
+}
+
+/// Secrets kept in the OS keyring (Keychain, Credential Manager, Secret
+/// Service) where one is available, and otherwise in a file in the app's data
+/// directory, encrypted with a key kept in the user's config directory. Both
+/// files are readable by the user only, so the file is obfuscation rather than
+/// protection: anyone who can read one can read the other.
+///
+/// The keyring calls block, and may wait on an unlock prompt, so run them
+/// off the async runtime.
+#[derive(Debug, Clone)]
+pub struct SecretStore {
+    dir: PathBuf,
+    key_dir: PathBuf,
+}
+
+impl SecretStore {
+    pub fn new(dir: PathBuf) -> Self {
+        let key_dir = dirs::config_dir().map(|config| config.join(SERVICE)).unwrap_or_else(|| dir.clone());
+        Self { dir, key_dir }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<String>> {
+        // Not in the keyring, or there is no keyring here
+        if let Ok(secret) = Entry::new(SERVICE, name).and_then(|entry| entry.get_password()) {
+            return Ok(Some(secret));
+        }
+        Ok(self.read_file()?.remove(name))
+    }
+
+    pub fn set(&self, name: &str, secret: &str) -> Result<SecretBackend> {
+        match Entry::new(SERVICE, name).and_then(|entry| entry.set_password(secret)) {
+            Ok(()) => {
+                // Don't leave an older copy behind in the file
+                self.remove_from_file(name)?;
+                return Ok(SecretBackend::Keyring);
+            }
+            Err(err) => eprintln!("OS keyring unavailable, storing '{}' in an encrypted file: {}", name, err),
+        }
+
+        let mut secrets = self.read_for_update()?;
+        secrets.insert(name.to_string(), secret.to_string());
+        self.write_file(&secrets)?;
+        Ok(SecretBackend::EncryptedFile)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        match Entry::new(SERVICE, name).and_then(|entry| entry.delete_credential()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(err) => eprintln!("Failed to remove '{}' from the OS keyring: {}", name, err),
+        }
+        self.remove_from_file(name)
+    }
+
+    fn remove_from_file(&self, name: &str) -> Result<()> {
+        let mut secrets = self.read_for_update()?;
+        if secrets.remove(name).is_some() {
+            self.write_file(&secrets)?;
+        }
+        Ok(())
+    }
+
+    fn read_file(&self) -> Result<BTreeMap<String, String>> {
+        let path = self.dir.join(SECRETS_FILE);
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let sealed = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let key = self.read_key().context("The key for the secrets file is missing")?;
+        if key.len() != 32 || sealed.len() < NONCE_LEN {
+            return Err(Damaged(format!("{} is damaged", path.display())).into());
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Damaged(format!("Failed to decrypt {}", path.display())))?;
+        serde_json::from_slice(&plaintext).map_err(|_| Damaged(format!("{} is damaged", path.display())).into())
+    }
+
+    /// The secrets to add to or remove from. A damaged file is moved aside
+    /// and started over; any other error, such as a missing key or a failed
+    /// read, is returned so the file is never written over.
+    fn read_for_update(&self) -> Result<BTreeMap<String, String>> {
+        match self.read_file() {
+            Err(err) if err.is::<Damaged>() => {
+                let path = self.dir.join(SECRETS_FILE);
+                let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                let backup = self.dir.join(format!("{}.{}.corrupt", SECRETS_FILE, stamp));
+                fs::rename(&path, &backup).with_context(|| format!("Failed to move {} aside", path.display()))?;
+                eprintln!("{}; moved it to {} and starting a new one", err, backup.display());
+                Ok(BTreeMap::new())
+            }
+            result => result,
+        }
+    }
+
+    /// The key the file was written with: beside it for files written by
+    /// older versions, otherwise in the config directory.
+    fn read_key(&self) -> Result<Vec<u8>> {
+        fs::read(self.dir.join(KEY_FILE)).or_else(|_| fs::read(self.key_dir.join(KEY_FILE))).map_err(Into::into)
+    }
+
+    fn write_file(&self, secrets: &BTreeMap<String, String>) -> Result<()> {
+        let path = self.dir.join(SECRETS_FILE);
+        let legacy_key_path = self.dir.join(KEY_FILE);
+        if secrets.is_empty() {
+            // The key in the config directory may be in use for another
+            // database's secrets, so it stays
+            for path in [&path, &legacy_key_path] {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let key_path = self.key_dir.join(KEY_FILE);
+        let key = match fs::read(&key_path) {
+            Ok(key) if key.len() == 32 => Key::clone_from_slice(&key),
+            _ => {
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                fs::create_dir_all(&self.key_dir)?;
+                write_private(&key_path, &key)?;
+                key
+            }
+        };
+
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&key)
+            .encrypt(&nonce, serde_json::to_vec(secrets)?.as_slice())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt secrets"))?;
+        write_private(&path, &[nonce.as_slice(), &ciphertext].concat())?;
+        if legacy_key_path != key_path && legacy_key_path.exists() {
+            fs::remove_file(&legacy_key_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The secrets file and its key were read, but don't make a valid file.
+#[derive(Debug)]
+struct Damaged(String);
+
+impl fmt::Display for Damaged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Damaged {}
+
+/// Name a credential profile's key is stored under.
+pub fn profile_key(profile_id: &str) -> String {
+    format!("{}:{}", API_KEY, profile_id)
+}
+
+/// The API key to start with: the saved one, or else `ANTHROPIC_API_KEY`.
+pub fn load_api_key(store: &SecretStore) -> Option<String> {
+    match store.get(API_KEY) {
+        Ok(Some(api_key)) => return Some(api_key),
+        Ok(None) => {}
+        Err(err) => eprintln!("Failed to read the saved API key: {:#}", err),
+    }
+    env::var("ANTHROPIC_API_KEY").ok()
+}
+
+/// Write a file only the user can read, replacing it in one step.
+fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(bytes))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
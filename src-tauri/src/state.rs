@@ -1,22 +1,125 @@
+use crate::api_config::ApiConfig;
+use crate::error::{AppError, Result};
+use crate::secrets::SecretStore;
+use crate::tools::{builtin_tools, McpManager, ToolRegistry};
 use parking_lot::Mutex;
 use sqlx::SqlitePool;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: SqlitePool,
     pub api_key: Arc<Mutex<Option<String>>>,
+    /// Endpoint, headers and HTTP client for requests to the Anthropic API, or
+    /// why the settings for them are invalid
+    api: std::result::Result<ApiConfig, AppError>,
+    pub secrets: SecretStore,
+    /// Keys of credential profiles read from the secret store so far, by profile id
+    pub profile_keys: Arc<Mutex<HashMap<String, String>>>,
+    /// In-flight generations keyed by chat id
+    pub generations: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+    /// Local tools offered to the model when a project enables them
+    pub tools: ToolRegistry,
+    pub mcp: McpManager,
 }
 
 impl AppState {
-    pub fn new(db: SqlitePool, api_key: Option<String>) -> Self {
+    pub fn new(db: SqlitePool, api: Result<ApiConfig>, secrets: SecretStore, api_key: Option<String>) -> Self {
         Self {
+            tools: builtin_tools(db.clone()),
             db,
             api_key: Arc::new(Mutex::new(api_key)),
+            api,
+            secrets,
+            profile_keys: Arc::new(Mutex::new(HashMap::new())),
+            generations: Arc::new(Mutex::new(HashMap::new())),
+            mcp: McpManager::default(),
         }
     }
 
+    /// Fails with the configuration error when the API settings are invalid.
+    pub fn api(&self) -> Result<&ApiConfig> {
+        self.api.as_ref().map_err(Clone::clone)
+    }
+
     pub fn get_api_key(&self) -> Option<String> {
         self.api_key.lock().clone()
     }
+
+    /// Replace the key used for every request from now on.
+    pub fn set_api_key(&self, api_key: Option<String>) {
+        *self.api_key.lock() = api_key;
+    }
+
+    /// Register a generation for the chat. Returns `None` if one is already
+    /// running. The generation ends when the returned guard is dropped.
+    pub fn begin_generation(&self, chat_id: &str) -> Option<Generation> {
+        let mut generations = self.generations.lock();
+        if generations.contains_key(chat_id) {
+            return None;
+        }
+
+        let cancel = Arc::new(Notify::new());
+        generations.insert(chat_id.to_string(), cancel.clone());
+        Some(Generation {
+            generations: self.generations.clone(),
+            chat_id: chat_id.to_string(),
+            cancel,
+        })
+    }
+
+    /// Signal the chat's in-flight generation to stop. Returns `false` if none is running.
+    pub fn cancel_generation(&self, chat_id: &str) -> bool {
+        match self.generations.lock().get(chat_id) {
+            Some(cancel) => {
+                cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Signal every in-flight generation to stop. Returns `false` if none are running.
+    pub fn cancel_all_generations(&self) -> bool {
+        let generations = self.generations.lock();
+        for cancel in generations.values() {
+            cancel.notify_one();
+        }
+        !generations.is_empty()
+    }
+
+    pub fn has_generations(&self) -> bool {
+        !self.generations.lock().is_empty()
+    }
+
+    /// Signal every in-flight generation to stop and wait, up to two seconds,
+    /// for them to save what they have.
+    pub async fn stop_all_generations(&self) {
+        if !self.cancel_all_generations() {
+            return;
+        }
+        for _ in 0..40 {
+            if !self.has_generations() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+/// A chat's in-flight generation, registered until this is dropped, so every
+/// early return releases the chat.
+pub struct Generation {
+    generations: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+    chat_id: String,
+    pub cancel: Arc<Notify>,
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        self.generations.lock().remove(&self.chat_id);
+    }
 }
@@ -0,0 +1,74 @@
+use super::ToolRegistry;
+use crate::db;
+use crate::error::{AppError, Result};
+use chrono::{Local, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+
+const MAX_SEARCH_RESULTS: u32 = 20;
+
+#[derive(Deserialize)]
+struct SearchInput {
+    query: String,
+    #[serde(default)]
+    limit: Option<u32>,
+}
+
+fn parse_input<T: for<'de> Deserialize<'de>>(input: serde_json::Value) -> Result<T> {
+    serde_json::from_value(input).map_err(|e| AppError::invalid_input(format!("Invalid tool input: {}", e)))
+}
+
+/// Tools that ship with the app.
+pub fn builtin_tools(pool: SqlitePool) -> ToolRegistry {
+    let mut registry = ToolRegistry::default();
+
+    registry.register(
+        "get_current_time",
+        "Get the current date and time on the user's computer, in local time and UTC.",
+        json!({ "type": "object", "properties": {} }),
+        |_| async {
+            Ok(json!({
+                "local": Local::now().to_rfc3339(),
+                "utc": Utc::now().to_rfc3339(),
+            })
+            .to_string())
+        },
+    );
+
+    registry.register(
+        "search_conversations",
+        "Full-text search over the user's past conversations in this app. Returns matching snippets with chat titles and dates.",
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Words to search for" },
+                "limit": { "type": "integer", "description": "Maximum number of results (default 10)" }
+            },
+            "required": ["query"]
+        }),
+        move |input| {
+            let pool = pool.clone();
+            async move {
+                let input: SearchInput = parse_input(input)?;
+                let limit = input.limit.unwrap_or(10).min(MAX_SEARCH_RESULTS);
+                let hits = db::search(&pool, &input.query, None, None, None, None, limit).await?;
+
+                let results: Vec<_> = hits
+                    .into_iter()
+                    .map(|hit| {
+                        json!({
+                            "chat_title": hit.chat_title,
+                            "role": hit.role,
+                            "snippet": hit.snippet,
+                            "created_at": hit.created_at,
+                        })
+                    })
+                    .collect();
+                Ok(serde_json::to_string(&results)?)
+            }
+        },
+    );
+
+    registry
+}
@@ -0,0 +1,392 @@
+use super::ToolRegistry;
+use crate::db::models::McpServerConfig;
+use crate::error::{AppError, Result};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::oneshot;
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const TOOL_CALL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The API only accepts tool names matching `^[a-zA-Z0-9_-]{1,64}$`
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>;
+
+#[derive(Debug, Clone, Deserialize)]
+struct McpTool {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(rename = "inputSchema", default = "empty_schema")]
+    input_schema: Value,
+}
+
+fn empty_schema() -> Value {
+    json!({ "type": "object", "properties": {} })
+}
+
+#[derive(Deserialize)]
+struct ListToolsResult {
+    tools: Vec<McpTool>,
+    #[serde(rename = "nextCursor", default)]
+    next_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallToolResult {
+    #[serde(default)]
+    content: Vec<Value>,
+    #[serde(rename = "structuredContent", default)]
+    structured_content: Option<Value>,
+    #[serde(rename = "isError", default)]
+    is_error: bool,
+}
+
+/// A running MCP server. Messages are newline-delimited JSON-RPC on the
+/// child's stdin and stdout; stderr is forwarded to ours.
+pub struct McpClient {
+    name: String,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Pending,
+    next_id: AtomicU64,
+    closed: Arc<AtomicBool>,
+    tools: Vec<McpTool>,
+    child: Mutex<Child>,
+}
+
+impl McpClient {
+    /// Start the server, perform the `initialize` handshake and fetch its tools.
+    pub async fn spawn(config: &McpServerConfig) -> Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| AppError::tool(format!("Failed to start MCP server '{}': {}", config.name, e)))?;
+
+        let (Some(stdin), Some(stdout), Some(stderr)) = (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err(AppError::tool(format!("MCP server '{}' has no stdio", config.name)));
+        };
+
+        let mut client = Self {
+            name: config.name.clone(),
+            stdin: Arc::new(tokio::sync::Mutex::new(stdin)),
+            pending: Arc::default(),
+            next_id: AtomicU64::new(1),
+            closed: Arc::default(),
+            tools: Vec::new(),
+            child: Mutex::new(child),
+        };
+
+        let name = client.name.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                eprintln!("[mcp:{}] {}", name, line);
+            }
+        });
+
+        tokio::spawn(read_messages(
+            client.name.clone(),
+            BufReader::new(stdout),
+            client.stdin.clone(),
+            client.pending.clone(),
+            client.closed.clone(),
+        ));
+
+        client
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": { "name": "claude-lite", "version": env!("CARGO_PKG_VERSION") },
+                }),
+                REQUEST_TIMEOUT,
+            )
+            .await?;
+        client
+            .send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+            .await?;
+
+        client.tools = client.list_tools().await?;
+        Ok(client)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Kill the server now, rather than once the last request holding it ends.
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Err(err) = self.child.lock().start_kill() {
+            eprintln!("Failed to stop MCP server '{}': {}", self.name, err);
+        }
+    }
+
+    async fn list_tools(&self) -> Result<Vec<McpTool>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self.request("tools/list", params, REQUEST_TIMEOUT).await?;
+            let page: ListToolsResult = serde_json::from_value(result)?;
+            tools.extend(page.tools);
+
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    /// Call one of the server's tools, flattening its content to text.
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<String> {
+        let result = self
+            .request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+                TOOL_CALL_TIMEOUT,
+            )
+            .await?;
+        let result: CallToolResult = serde_json::from_value(result)?;
+
+        let mut parts: Vec<String> = result.content.iter().map(content_text).collect();
+        if parts.is_empty() {
+            if let Some(structured) = result.structured_content {
+                parts.push(structured.to_string());
+            }
+        }
+
+        let text = parts.join("\n");
+        if result.is_error {
+            return Err(AppError::tool(text));
+        }
+        Ok(text)
+    }
+
+    /// Add the server's tools to `registry`, prefixed with the server name.
+    pub fn register_tools(self: &Arc<Self>, registry: &mut ToolRegistry) {
+        for tool in &self.tools {
+            let client = self.clone();
+            let tool_name = tool.name.clone();
+
+            registry.register(
+                qualified_name(&self.name, &tool.name),
+                tool.description.clone().unwrap_or_else(|| tool.name.clone()),
+                tool.input_schema.clone(),
+                move |input| {
+                    let client = client.clone();
+                    let tool_name = tool_name.clone();
+                    async move { client.call_tool(&tool_name, input).await }
+                },
+            );
+        }
+    }
+
+    async fn request(&self, method: &str, params: Value, timeout: Duration) -> Result<Value> {
+        if self.is_closed() {
+            return Err(exited(&self.name));
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().insert(id, tx);
+        let _pending = PendingGuard {
+            pending: &self.pending,
+            id,
+        };
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.send(message).await?;
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(exited(&self.name)),
+            Err(_) => Err(AppError::tool(format!(
+                "MCP server '{}' did not answer {} within {}s",
+                self.name,
+                method,
+                timeout.as_secs()
+            ))),
+        }
+    }
+
+    async fn send(&self, message: Value) -> Result<()> {
+        write_message(&self.name, &self.stdin, &message).await
+    }
+}
+
+/// Removes a request's `pending` entry however waiting for it ends, including
+/// the caller being dropped when its generation is cancelled.
+struct PendingGuard<'a> {
+    pending: &'a Pending,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().remove(&self.id);
+    }
+}
+
+async fn write_message(server: &str, stdin: &tokio::sync::Mutex<ChildStdin>, message: &Value) -> Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+
+    let mut stdin = stdin.lock().await;
+    let written = async {
+        stdin.write_all(line.as_bytes()).await?;
+        stdin.flush().await
+    };
+    written
+        .await
+        .map_err(|e| AppError::tool(format!("Failed to write to MCP server '{}': {}", server, e)))
+}
+
+/// Route responses to their waiting requests and answer the server's own
+/// requests until stdout closes.
+async fn read_messages(
+    server: String,
+    stdout: BufReader<tokio::process::ChildStdout>,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Pending,
+    closed: Arc<AtomicBool>,
+) {
+    let mut lines = stdout.lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        let id = message.get("id").cloned();
+
+        match (message.get("method").and_then(Value::as_str), id) {
+            // Requests from the server. Only ping is supported.
+            (Some(method), Some(id)) => {
+                let reply = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("Method not found: {}", method) },
+                    })
+                };
+                let _ = write_message(&server, &stdin, &reply).await;
+            }
+            // Notifications need no answer
+            (Some(_), None) => {}
+            (None, Some(id)) => {
+                let Some(tx) = id.as_u64().and_then(|id| pending.lock().remove(&id)) else {
+                    continue;
+                };
+                let result = match message.get("error") {
+                    Some(error) => Err(AppError::tool(format!(
+                        "MCP server '{}': {}",
+                        server,
+                        error.get("message").and_then(Value::as_str).unwrap_or("unknown error")
+                    ))),
+                    None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                };
+                let _ = tx.send(result);
+            }
+            (None, None) => {}
+        }
+    }
+
+    closed.store(true, Ordering::SeqCst);
+    for (_, tx) in pending.lock().drain() {
+        let _ = tx.send(Err(exited(&server)));
+    }
+}
+
+fn exited(server: &str) -> AppError {
+    AppError::tool(format!("MCP server '{}' exited", server))
+}
+
+fn content_text(content: &Value) -> String {
+    let field = |name: &str| content.get(name).and_then(Value::as_str).unwrap_or_default();
+
+    match field("type") {
+        "text" => field("text").to_string(),
+        "resource" => {
+            let resource = &content["resource"];
+            match resource.get("text").and_then(Value::as_str) {
+                Some(text) => text.to_string(),
+                None => format!("[resource: {}]", resource["uri"].as_str().unwrap_or_default()),
+            }
+        }
+        "resource_link" => format!("[resource: {}]", field("uri")),
+        other => format!("[{}: {}]", other, field("mimeType")),
+    }
+}
+
+/// `server__tool`, restricted to the characters and length the API allows.
+fn qualified_name(server: &str, tool: &str) -> String {
+    format!("{}__{}", server, tool)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .take(MAX_TOOL_NAME_LEN)
+        .collect()
+}
+
+/// A server's client and the configuration it was started with, locked while
+/// it is being started so concurrent chats wait for one process rather than
+/// each spawning their own
+type McpSlot = Arc<tokio::sync::Mutex<Option<(String, Arc<McpClient>)>>>;
+
+/// The project, or none for chats outside one, and the server's name
+type McpKey = (Option<String>, String);
+
+/// Running MCP servers, shared across chats and keyed by project and server
+/// name. A settings change stops the server and starts a fresh process.
+#[derive(Clone, Default)]
+pub struct McpManager {
+    clients: Arc<Mutex<HashMap<McpKey, McpSlot>>>,
+}
+
+impl McpManager {
+    pub async fn connect(&self, project_id: Option<&str>, config: &McpServerConfig) -> Result<Arc<McpClient>> {
+        let key = (project_id.map(str::to_string), config.name.clone());
+        let slot = self.clients.lock().entry(key).or_default().clone();
+        let config_key = serde_json::to_string(config)?;
+
+        let mut slot = slot.lock().await;
+        match slot.take() {
+            Some((started_with, client)) if started_with == config_key && !client.is_closed() => {
+                *slot = Some((started_with, client.clone()));
+                return Ok(client);
+            }
+            Some((_, client)) => client.close(),
+            None => {}
+        }
+
+        let client = Arc::new(McpClient::spawn(config).await?);
+        *slot = Some((config_key, client.clone()));
+        Ok(client)
+    }
+
+    /// Stop every server. Children are killed once no request holds them.
+    pub fn shutdown(&self) {
+        self.clients.lock().clear();
+    }
+}
@@ -0,0 +1,79 @@
+mod builtin;
+mod mcp;
+
+pub use builtin::builtin_tools;
+pub use mcp::McpManager;
+
+use crate::error::{AppError, Result};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+
+type Handler = Arc<dyn Fn(serde_json::Value) -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+/// A tool as advertised to the model in the request's `tools` array.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub input_schema: serde_json::Value,
+}
+
+#[derive(Clone)]
+struct Tool {
+    definition: ToolDefinition,
+    handler: Handler,
+}
+
+/// Tools the model may call, keyed by name. Cheap to clone, so a request can
+/// start from the shared registry and add its own tools.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Tool>,
+}
+
+impl ToolRegistry {
+    /// Register a tool. `input_schema` is a JSON Schema object describing the
+    /// handler's input; a tool with the same name is replaced.
+    pub fn register<F, Fut>(
+        &mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        input_schema: serde_json::Value,
+        handler: F,
+    ) where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        let name = name.into();
+        let handler: Handler = Arc::new(move |input| Box::pin(handler(input)));
+        self.tools.insert(
+            name.clone(),
+            Tool {
+                definition: ToolDefinition {
+                    name,
+                    description: description.into(),
+                    input_schema,
+                },
+                handler,
+            },
+        );
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.values().map(|tool| tool.definition.clone()).collect()
+    }
+
+    /// Run the named tool. Errors become `is_error` tool results, so the model
+    /// can see what went wrong and try again.
+    pub async fn call(&self, name: &str, input: serde_json::Value) -> Result<String> {
+        let tool = self
+            .tools
+            .get(name)
+            .ok_or_else(|| AppError::invalid_input(format!("Unknown tool: {}", name)))?;
+
+        (tool.handler)(input).await
+    }
+}
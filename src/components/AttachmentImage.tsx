@@ -0,0 +1,39 @@
+import { useEffect, useState } from 'react';
+import * as api from '../lib/tauri';
+import type { MessageAttachment } from '../lib/types';
+
+// Data URLs by content hash, so scrolling back over a chat doesn't refetch
+const loaded = new Map<string, string>();
+
+export default function AttachmentImage({ attachment }: { attachment: MessageAttachment }) {
+  const [src, setSrc] = useState(() =>
+    attachment.data ? `data:${attachment.media_type};base64,${attachment.data}` : loaded.get(attachment.sha256)
+  );
+
+  useEffect(() => {
+    if (src || !attachment.sha256) return;
+    let cancelled = false;
+    api
+      .getAttachment(attachment.sha256)
+      .then(({ media_type, data }) => {
+        const url = `data:${media_type};base64,${data}`;
+        loaded.set(attachment.sha256, url);
+        if (!cancelled) setSrc(url);
+      })
+      .catch((e) => console.error('Failed to load attachment:', e));
+    return () => {
+      cancelled = true;
+    };
+  }, [attachment.sha256, src]);
+
+  if (!src) {
+    return <div className="w-32 h-32 rounded-lg border border-slate-600 bg-slate-800/50 animate-pulse" />;
+  }
+  return (
+    <img
+      src={src}
+      alt={attachment.name || 'Attached'}
+      className="max-w-xs max-h-64 object-contain rounded-lg border border-slate-600"
+    />
+  );
+}
@@ -3,10 +3,11 @@ import { useChatsStore } from '../store/chats';
 import ReactMarkdown from 'react-markdown';
 import hljs from 'highlight.js';
 import MessageInput from './MessageInput';
+import AttachmentImage from './AttachmentImage';
 import 'highlight.js/styles/github-dark.css';
 
 export default function ChatView() {
-  const { currentChat, messages, streamingContent, isThinking, isSending } = useChatsStore();
+  const { currentChat, messages, streamingContent, isThinking, isSending, retryAttempt, runningTool, omittedMessageIds, historySummarized } = useChatsStore();
   const messagesEndRef = useRef<HTMLDivElement>(null);
 
   const scrollToBottom = () => {
@@ -52,7 +53,10 @@ export default function ChatView() {
           {messages.map((message) => (
             <div
               key={message.id}
-              className={`flex ${message.role === 'user' ? 'justify-end' : 'justify-start'} animate-in fade-in slide-in-from-bottom-2 duration-300`}
+              title={omittedMessageIds.includes(message.id)
+                ? historySummarized ? 'Summarised to fit the context window' : 'Left out to fit the context window'
+                : undefined}
+              className={`flex ${message.role === 'user' ? 'justify-end' : 'justify-start'} ${omittedMessageIds.includes(message.id) ? 'opacity-50' : ''} animate-in fade-in slide-in-from-bottom-2 duration-300`}
             >
               <div
                 className={`rounded-2xl px-5 py-3 w-full ${
@@ -89,59 +93,34 @@ export default function ChatView() {
                 )}
 
                 {/* Display images if present */}
-                {message.images && (() => {
-                  try {
-                    const images = typeof message.images === 'string'
-                      ? JSON.parse(message.images)
-                      : message.images;
-                    if (Array.isArray(images) && images.length > 0) {
-                      return (
-                        <div className="flex flex-wrap gap-2 mb-3">
-                          {images.map((image: any, idx: number) => (
-                            <img
-                              key={idx}
-                              src={`data:${image.media_type};base64,${image.data}`}
-                              alt="Attached"
-                              className="max-w-xs max-h-64 object-contain rounded-lg border border-slate-600"
-                            />
-                          ))}
-                        </div>
-                      );
-                    }
-                  } catch (e) {
-                    console.error('Failed to parse images:', e);
-                  }
-                  return null;
-                })()}
+                {message.attachments?.some((a) => a.kind === 'image') && (
+                  <div className="flex flex-wrap gap-2 mb-3">
+                    {message.attachments
+                      .filter((a) => a.kind === 'image')
+                      .map((image, idx) => (
+                        <AttachmentImage key={`${image.sha256}-${idx}`} attachment={image} />
+                      ))}
+                  </div>
+                )}
 
                 {/* Display documents if present */}
-                {message.documents && (() => {
-                  try {
-                    const documents = typeof message.documents === 'string'
-                      ? JSON.parse(message.documents)
-                      : message.documents;
-                    if (Array.isArray(documents) && documents.length > 0) {
-                      return (
-                        <div className="flex flex-wrap gap-2 mb-3">
-                          {documents.map((doc: any, idx: number) => (
-                            <div
-                              key={idx}
-                              className="flex items-center gap-2 px-3 py-2 bg-slate-800/50 rounded-lg border border-slate-600"
-                            >
-                              <svg className="w-5 h-5 text-blue-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M9 12h6m-6 4h6m2 5H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z" />
-                              </svg>
-                              <span className="text-sm text-slate-300">{doc.name}</span>
-                            </div>
-                          ))}
+                {message.attachments?.some((a) => a.kind === 'document') && (
+                  <div className="flex flex-wrap gap-2 mb-3">
+                    {message.attachments
+                      .filter((a) => a.kind === 'document')
+                      .map((doc, idx) => (
+                        <div
+                          key={`${doc.sha256}-${idx}`}
+                          className="flex items-center gap-2 px-3 py-2 bg-slate-800/50 rounded-lg border border-slate-600"
+                        >
+                          <svg className="w-5 h-5 text-blue-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                            <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M9 12h6m-6 4h6m2 5H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z" />
+                          </svg>
+                          <span className="text-sm text-slate-300">{doc.name}</span>
                         </div>
-                      );
-                    }
-                  } catch (e) {
-                    console.error('Failed to parse documents:', e);
-                  }
-                  return null;
-                })()}
+                      ))}
+                  </div>
+                )}
                 <div className={`prose prose-invert max-w-none ${message.role === 'user' ? 'prose-p:text-white prose-headings:text-white prose-strong:text-white' : ''}`}>
                   <ReactMarkdown
                     components={{
@@ -203,7 +182,10 @@ export default function ChatView() {
                     <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M9.663 17h4.673M12 3v1m6.364 1.636l-.707.707M21 12h-1M4 12H3m3.343-5.657l-.707-.707m2.828 9.9a5 5 0 117.072 0l-.548.547A3.374 3.374 0 0014 18.469V19a2 2 0 11-4 0v-.531c0-.895-.356-1.754-.988-2.386l-.548-.547z" />
                   </svg>
                   <span className="text-xs font-semibold text-slate-400 uppercase tracking-wider">Claude</span>
-                  <span className="text-xs text-slate-500">{isThinking ? 'thinking...' : 'typing...'}</span>
+                  <span className="text-xs text-slate-500">{retryAttempt
+                      ? `retrying (${retryAttempt.attempt}/${retryAttempt.maxRetries})...`
+                      : runningTool ? `using ${runningTool}...`
+                      : isThinking ? 'thinking...' : 'typing...'}</span>
                 </div>
                 {streamingContent ? (
                   <>
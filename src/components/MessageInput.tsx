@@ -1,4 +1,5 @@
 import { useState, useRef, useEffect } from 'react';
+import { getCurrentWebview } from '@tauri-apps/api/webview';
 import { useChatsStore } from '../store/chats';
 import { useProjectsStore } from '../store/projects';
 
@@ -12,9 +13,11 @@ const MODELS = [
 interface AttachedFile {
   id: string;
   dataUrl: string;
-  file: File;
-  type: 'image' | 'document' | 'text';
-  textContent?: string;
+  name: string;
+  file?: File;
+  // Dropped from the file system; the backend reads and checks it
+  path?: string;
+  type: 'image' | 'document' | 'text' | 'path';
 }
 
 export default function MessageInput() {
@@ -22,7 +25,7 @@ export default function MessageInput() {
   const [showModelSelector, setShowModelSelector] = useState(false);
   const [extendedThinking, setExtendedThinking] = useState(false);
   const [attachedFiles, setAttachedFiles] = useState<AttachedFile[]>([]);
-  const { currentChat, sendMessage, isSending } = useChatsStore();
+  const { currentChat, sendMessage, cancelGeneration, isSending } = useChatsStore();
   const { currentProject } = useProjectsStore();
   const [selectedModel, setSelectedModel] = useState(MODELS[0]);
   const textareaRef = useRef<HTMLTextAreaElement>(null);
@@ -46,6 +49,24 @@ export default function MessageInput() {
     }
   }, [currentChat]);
 
+  useEffect(() => {
+    // Dropped files arrive as paths, so large ones never pass through the webview
+    const unlisten = getCurrentWebview().onDragDropEvent(({ payload }) => {
+      if (payload.type !== 'drop') return;
+      const dropped = payload.paths.map((path) => ({
+        id: Math.random().toString(36).substr(2, 9),
+        dataUrl: '',
+        name: path.split(/[\\/]/).pop() || path,
+        path,
+        type: 'path' as const,
+      }));
+      setAttachedFiles(prev => [...prev, ...dropped]);
+    });
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
   const isImageFile = (file: File) => file.type.startsWith('image/');
   const isDocumentFile = (file: File) => {
     // API only supports PDF for document type
@@ -53,7 +74,7 @@ export default function MessageInput() {
   };
   const isTextFile = (file: File) => {
     const textTypes = ['text/plain', 'text/markdown', 'text/csv', 'text/html', 'application/json'];
-    const textExtensions = ['.txt', '.md', '.csv', '.html', '.json', '.js', '.ts', '.tsx', '.jsx', '.css', '.py', '.java', '.c', '.cpp', '.rs', '.go'];
+    const textExtensions = ['.txt', '.md', '.csv', '.tsv', '.html', '.json', '.js', '.ts', '.tsx', '.jsx', '.css', '.py', '.java', '.c', '.h', '.cpp', '.hpp', '.cs', '.rs', '.go', '.rb', '.php', '.kt', '.swift', '.sql', '.sh', '.toml', '.yaml', '.yml', '.xml', '.diff'];
     return textTypes.includes(file.type) || textExtensions.some(ext => file.name.toLowerCase().endsWith(ext));
   };
 
@@ -95,6 +116,7 @@ export default function MessageInput() {
         setAttachedFiles(prev => [...prev, {
           id: Math.random().toString(36).substr(2, 9),
           dataUrl,
+          name: file.name,
           file,
           type: 'image'
         }]);
@@ -113,6 +135,7 @@ export default function MessageInput() {
         setAttachedFiles(prev => [...prev, {
           id: Math.random().toString(36).substr(2, 9),
           dataUrl,
+          name: file.name,
           file,
           type: 'document'
         }]);
@@ -125,21 +148,19 @@ export default function MessageInput() {
         alert('Text file must be under 1MB');
         return;
       }
+      // Sent as raw bytes; the backend detects the encoding
       const reader = new FileReader();
       reader.onload = (event) => {
-        const textContent = event.target?.result as string;
-        // Create a data URL for preview (not sent to API)
-        const blob = new Blob([textContent], { type: 'text/plain' });
-        const dataUrl = URL.createObjectURL(blob);
+        const dataUrl = event.target?.result as string;
         setAttachedFiles(prev => [...prev, {
           id: Math.random().toString(36).substr(2, 9),
           dataUrl,
+          name: file.name,
           file,
-          type: 'text',
-          textContent
+          type: 'text'
         }]);
       };
-      reader.readAsText(file);
+      reader.readAsDataURL(file);
     } else {
       alert('Unsupported file type. Supported: Images (JPEG, PNG, GIF, WebP), PDF documents, and text files (TXT, MD, CSV, HTML, JSON, code files)');
     }
@@ -153,32 +174,28 @@ export default function MessageInput() {
     e.preventDefault();
     if ((!input.trim() && attachedFiles.length === 0) || !currentChat || isSending) return;
 
-    let messageContent = input.trim();
-
-    // Add text file contents to message
-    const textFiles = attachedFiles.filter(f => f.type === 'text');
-    if (textFiles.length > 0) {
-      const textFilesContent = textFiles
-        .map(f => `\n\n---\nFile: ${f.file.name}\n\`\`\`\n${f.textContent}\n\`\`\``)
-        .join('\n');
-      messageContent = messageContent + textFilesContent;
-    }
+    const messageContent = input.trim();
 
     const images = attachedFiles
       .filter(f => f.type === 'image')
       .map(f => ({
         data: f.dataUrl.split(',')[1],
-        media_type: f.file.type
+        media_type: f.file!.type
       }));
 
+    // Text files go as documents too, and reach the model as plain text
     const documents = attachedFiles
-      .filter(f => f.type === 'document')
+      .filter(f => f.type === 'document' || f.type === 'text')
       .map(f => ({
         data: f.dataUrl.split(',')[1],
-        media_type: f.file.type,
-        name: f.file.name
+        media_type: f.type === 'text' ? 'text/plain' : f.file!.type,
+        name: f.name
       }));
 
+    const filePaths = attachedFiles
+      .filter(f => f.type === 'path')
+      .map(f => f.path!);
+
     setInput('');
     setAttachedFiles([]);
 
@@ -188,7 +205,7 @@ export default function MessageInput() {
       textareaRef.current.blur();
     }
 
-    await sendMessage(messageContent, currentProject?.id, selectedModel.id, images, extendedThinking, documents);
+    await sendMessage(messageContent, currentProject?.id, selectedModel.id, images, extendedThinking, documents, filePaths);
   };
 
   const handleKeyDown = (e: React.KeyboardEvent<HTMLTextAreaElement>) => {
@@ -233,7 +250,7 @@ export default function MessageInput() {
                         <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M9 12h6m-6 4h6m2 5H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z" />
                       </svg>
                       <span className="text-xs text-green-400 text-center truncate w-full">
-                        {file.file.name.split('.').pop()?.toUpperCase()}
+                        {file.name.split('.').pop()?.toUpperCase()}
                       </span>
                     </div>
                   ) : (
@@ -242,7 +259,7 @@ export default function MessageInput() {
                         <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M9 12h6m-6 4h6m2 5H7a2 2 0 01-2-2V5a2 2 0 012-2h5.586a1 1 0 01.707.293l5.414 5.414a1 1 0 01.293.707V19a2 2 0 01-2 2z" />
                       </svg>
                       <span className="text-xs text-slate-400 text-center truncate w-full">
-                        {file.file.name.split('.').pop()?.toUpperCase()}
+                        {file.name.split('.').pop()?.toUpperCase()}
                       </span>
                     </div>
                   )}
@@ -268,7 +285,7 @@ export default function MessageInput() {
                 ref={fileInputRef}
                 type="file"
                 onChange={handleFileSelect}
-                accept="image/*,.pdf,.txt,.md,.csv,.html,.json,.js,.ts,.tsx,.jsx,.css,.py,.java,.c,.cpp,.rs,.go"
+                accept="image/*,.pdf,.txt,.md,.csv,.tsv,.html,.json,.js,.ts,.tsx,.jsx,.css,.py,.java,.c,.h,.cpp,.hpp,.cs,.rs,.go,.rb,.php,.kt,.swift,.sql,.sh,.toml,.yaml,.yml,.xml,.diff"
                 multiple
                 className="hidden"
               />
@@ -341,6 +358,20 @@ export default function MessageInput() {
                 )}
               </div>
 
+              {/* Stop Button */}
+              {isSending && (
+                <button
+                  type="button"
+                  onClick={() => cancelGeneration()}
+                  className="p-2 text-slate-300 rounded-lg transition-colors cursor-pointer bg-slate-800 hover:bg-slate-700"
+                  title="Stop generating"
+                >
+                  <svg className="w-5 h-5" fill="currentColor" viewBox="0 0 24 24">
+                    <rect x="6" y="6" width="12" height="12" rx="2" />
+                  </svg>
+                </button>
+              )}
+
               {/* Send Button */}
               <button
                 type="submit"
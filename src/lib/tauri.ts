@@ -1,6 +1,32 @@
-import { invoke } from '@tauri-apps/api/core';
-import { listen } from '@tauri-apps/api/event';
-import type { Chat, Message, MessageImage, MessageDocument, Project, ProjectSettings, StreamChunk } from './types';
+import { Channel, invoke } from '@tauri-apps/api/core';
+import type { ApiKeyStatus, AttachmentData, BackupInfo, Chat, Compaction, CredentialProfile, CredentialProfileInput, DailyUsage, ExportFormat, GenerationEvent, ImportSummary, Message, MessageImage, MessageDocument, ModelPrice, Project, ProjectDocument, ProjectSettings, SearchFilters, SecretBackend, SearchHit, ToolDefinition, UsageTotals } from './types';
+
+// API key
+export const getApiKeyStatus = () => invoke<ApiKeyStatus>('get_api_key_status');
+// Rejects with an 'authentication' error if the API refuses the key; checks the current key when none is given
+export const validateApiKey = (apiKey?: string) => invoke('validate_api_key', { apiKey: apiKey || null });
+export const setApiKey = (apiKey: string) => invoke<SecretBackend>('set_api_key', { apiKey });
+export const clearApiKey = () => invoke('clear_api_key');
+
+// Credential profiles
+export const listCredentialProfiles = () => invoke<CredentialProfile[]>('list_credential_profiles');
+export const createCredentialProfile = (profile: CredentialProfileInput & { api_key: string }) =>
+  invoke<CredentialProfile>('create_credential_profile', {
+    name: profile.name,
+    apiKey: profile.api_key,
+    baseUrl: profile.base_url || null,
+    headers: profile.headers ?? null,
+  });
+export const updateCredentialProfile = (profileId: string, profile: CredentialProfileInput) =>
+  invoke<CredentialProfile>('update_credential_profile', {
+    profileId,
+    name: profile.name,
+    apiKey: profile.api_key || null,
+    baseUrl: profile.base_url || null,
+    headers: profile.headers ?? null,
+  });
+export const deleteCredentialProfile = (profileId: string) => invoke('delete_credential_profile', { profileId });
+export const validateCredentialProfile = (profileId: string) => invoke('validate_credential_profile', { profileId });
 
 // Projects
 export const listProjects = () => invoke<Project[]>('list_projects');
@@ -11,6 +37,17 @@ export const getProjectSettings = (projectId: string) =>
 export const updateProjectSettings = (projectId: string, settings: ProjectSettings) =>
   invoke('update_project_settings', { projectId, settings });
 export const deleteProject = (projectId: string) => invoke('delete_project', { projectId });
+export const listProjectTools = (projectId: string) =>
+  invoke<ToolDefinition[]>('list_project_tools', { projectId });
+
+// Project knowledge base: documents come from a file path or from text
+export const listProjectDocuments = (projectId: string) =>
+  invoke<ProjectDocument[]>('list_project_documents', { projectId });
+export const addProjectDocument = (projectId: string, source: { path?: string; name?: string; text?: string }) =>
+  invoke<ProjectDocument>('add_project_document', { projectId, path: source.path || null, name: source.name || null, text: source.text ?? null });
+export const updateProjectDocument = (documentId: string, changes: { path?: string; name?: string; text?: string }) =>
+  invoke<ProjectDocument>('update_project_document', { documentId, path: changes.path || null, name: changes.name || null, text: changes.text ?? null });
+export const removeProjectDocument = (documentId: string) => invoke('remove_project_document', { documentId });
 
 // Chats
 export const listChats = (projectId?: string) =>
@@ -22,26 +59,100 @@ export const updateChatTitle = (chatId: string, title: string) =>
   invoke('update_chat_title', { chatId, title });
 export const deleteChat = (chatId: string) => invoke('delete_chat', { chatId });
 
+// Export
+export const exportChat = (chatId: string, format: ExportFormat, path: string) =>
+  invoke('export_chat', { chatId, format, path });
+export const exportProject = (projectId: string, format: ExportFormat, path: string) =>
+  invoke('export_project', { projectId, format, path });
+
+// Backups
+export const createBackup = () => invoke<BackupInfo>('create_backup');
+export const listBackups = () => invoke<BackupInfo[]>('list_backups');
+// Restarts the app once the backup is staged
+export const restoreBackup = (path: string) => invoke('restore_backup', { path });
+
+// Import
+export const importConversations = (path: string, projectId?: string) =>
+  invoke<ImportSummary>('import_conversations', { path, projectId: projectId || null });
+
 // Messages
-export const listMessages = (chatId: string) => invoke<Message[]>('list_messages', { chatId });
-export const sendMessage = (chatId: string, content: string, projectId?: string, model?: string, images?: MessageImage[], extendedThinking?: boolean, documents?: MessageDocument[]) =>
-  invoke<Message>('send_message', {
+// Attachment content is left out unless asked for; getAttachment loads it on demand
+export const listMessages = (chatId: string, includeAttachmentData?: boolean) =>
+  invoke<Message[]>('list_messages', { chatId, includeAttachmentData: includeAttachmentData || null });
+export const getAttachment = (sha256: string) => invoke<AttachmentData>('get_attachment', { sha256 });
+export const listCompactions = (chatId: string) => invoke<Compaction[]>('list_compactions', { chatId });
+export const sendMessage = (onEvent: (event: GenerationEvent) => void, chatId: string, content: string, projectId?: string, model?: string, images?: MessageImage[], extendedThinking?: boolean, documents?: MessageDocument[], filePaths?: string[]) => {
+  // Each generation streams over its own channel
+  const channel = new Channel<GenerationEvent>();
+  channel.onmessage = onEvent;
+
+  return invoke<Message>('send_message', {
+    onEvent: channel,
     chatId,
     content,
     projectId: projectId || null,
     model: model || null,
     images: images || null,
     extendedThinking: extendedThinking || false,
-    documents: documents || null
+    documents: documents || null,
+    // Images and PDFs read by the backend, which scales large images down
+    filePaths: filePaths || null
   });
+};
+export const editMessage = (onEvent: (event: GenerationEvent) => void, chatId: string, messageId: string, content: string, projectId?: string, model?: string, extendedThinking?: boolean) => {
+  const channel = new Channel<GenerationEvent>();
+  channel.onmessage = onEvent;
 
-// Streaming
-export const onStreamChunk = (callback: (chunk: StreamChunk) => void) => {
-  return listen<StreamChunk>('stream_chunk', (event: { payload: StreamChunk }) => {
-    callback(event.payload);
+  return invoke<Message>('edit_message', {
+    onEvent: channel,
+    chatId,
+    messageId,
+    content,
+    projectId: projectId || null,
+    model: model || null,
+    extendedThinking: extendedThinking || false,
+  });
+};
+export const regenerateMessage = (onEvent: (event: GenerationEvent) => void, chatId: string, messageId: string, projectId?: string, model?: string, extendedThinking?: boolean) => {
+  const channel = new Channel<GenerationEvent>();
+  channel.onmessage = onEvent;
+
+  return invoke<Message>('regenerate_message', {
+    onEvent: channel,
+    chatId,
+    messageId,
+    projectId: projectId || null,
+    model: model || null,
+    extendedThinking: extendedThinking || false,
   });
 };
+export const listBranches = (messageId: string) => invoke<Message[]>('list_branches', { messageId });
+export const switchBranch = (chatId: string, messageId: string) =>
+  invoke<Message[]>('switch_branch', { chatId, messageId });
+export const cancelGeneration = (chatId: string) => invoke<boolean>('cancel_generation', { chatId });
+
+// Search
+export const searchMessages = (query: string, filters: SearchFilters = {}) =>
+  invoke<SearchHit[]>('search_messages', {
+    query,
+    projectId: filters.projectId || null,
+    role: filters.role || null,
+    from: filters.from || null,
+    to: filters.to || null,
+    limit: filters.limit || null,
+  });
+
+// Usage
+export const getChatUsage = (chatId: string) => invoke<UsageTotals>('get_chat_usage', { chatId });
+export const getProjectUsage = (projectId?: string) =>
+  invoke<UsageTotals>('get_project_usage', { projectId: projectId || null });
+export const getDailyUsage = (projectId?: string, from?: string, to?: string) =>
+  invoke<DailyUsage[]>('get_daily_usage', { projectId: projectId || null, from: from || null, to: to || null });
+export const listModelPrices = () => invoke<ModelPrice[]>('list_model_prices');
+export const setModelPrice = (price: ModelPrice) => invoke('set_model_price', { price });
+export const deleteModelPrice = (model: string) => invoke('delete_model_price', { model });
 
 // AI Title Generation
-export const generateTitle = (userMessage: string, assistantResponse: string) =>
-  invoke<string>('generate_title', { userMessage, assistantResponse });
+// The chat's project decides which credentials are used
+export const generateTitle = (chatId: string | null, userMessage: string, assistantResponse: string) =>
+  invoke<string>('generate_title', { chatId, userMessage, assistantResponse });
@@ -11,12 +11,98 @@ export interface ProjectSettings {
   system_prompt?: string;
   max_tokens: number;
   temperature: number;
+  retry?: RetryPolicy;
+  tools_enabled?: boolean;
+  mcp_servers?: McpServerConfig[];
+  prompt_cache?: PromptCache;
+  context?: ContextSettings;
+  credential_profile?: string; // profile id; the default key when unset
+}
+
+// A named API key with its endpoint and default headers; the key itself is never returned
+export interface CredentialProfile {
+  id: string;
+  name: string;
+  base_url?: string; // https://api.anthropic.com when unset
+  headers: Record<string, string>;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface CredentialProfileInput {
+  name: string;
+  api_key?: string; // required when creating; kept when omitted on update
+  base_url?: string;
+  headers?: Record<string, string>;
+}
+
+// What happens to older turns once a chat outgrows the context window
+export interface ContextSettings {
+  strategy: 'truncate' | 'summarize'; // truncate by default
+  window_tokens?: number; // defaults to 200K
+}
+
+// A stored summary sent in place of the messages up to through_message_id
+export interface Compaction {
+  id: string;
+  chat_id: string;
+  through_message_id: string;
+  summary: string;
+  created_at: string;
+}
+
+// Cache breakpoints on the system prompt, knowledge base and conversation so far
+export interface PromptCache {
+  enabled: boolean; // on by default
+  ttl: '5m' | '1h';
+}
+
+// Whether an API key is set, saved or from the environment
+export interface ApiKeyStatus {
+  configured: boolean;
+  hint?: string; // last four characters
+}
+
+// Where a saved key ended up
+export type SecretBackend = 'keyring' | 'encrypted_file';
+
+// A file in a project's knowledge base, sent with every chat in the project
+export interface ProjectDocument {
+  id: string;
+  project_id: string;
+  name: string;
+  sha256: string;
+  media_type: string;
+  size_bytes: number;
+  token_count?: number; // missing until the API could count it
+  created_at: string;
+  updated_at: string;
+}
+
+export interface McpServerConfig {
+  name: string; // prefixes the server's tool names
+  command: string;
+  args?: string[];
+  env?: Record<string, string>;
+}
+
+export interface ToolDefinition {
+  name: string;
+  description: string;
+  input_schema: Record<string, unknown>;
+}
+
+export interface RetryPolicy {
+  max_retries: number;
+  initial_delay_ms: number;
+  max_delay_ms: number;
 }
 
 export interface Chat {
   id: string;
   project_id?: string;
   title: string;
+  active_leaf_id?: string;
   created_at: string;
   updated_at: string;
 }
@@ -32,19 +118,137 @@ export interface MessageDocument {
   name: string;
 }
 
+// A stored image or document; `data` is only present when asked for
+export interface MessageAttachment {
+  sha256: string;
+  kind: 'image' | 'document';
+  media_type: string;
+  name?: string;
+  size_bytes: number;
+  data?: string; // base64 encoded
+}
+
+export interface AttachmentData {
+  media_type: string;
+  data: string; // base64 encoded
+}
+
 export interface Message {
   id: string;
   chat_id: string;
+  parent_id?: string;
   role: 'user' | 'assistant';
   content: string;
-  images?: MessageImage[];
-  documents?: MessageDocument[];
+  attachments: MessageAttachment[];
   model?: string;
   extended_thinking?: boolean;
+  thinking?: string; // JSON-encoded ThinkingBlock[]
+  tool_turns?: string; // JSON-encoded API turns of a reply that called tools
+  stop_reason?: string; // e.g. 'end_turn', 'max_tokens', 'cancelled', 'error'
+  created_at: string;
+  usage?: MessageUsage;
+}
+
+export interface MessageUsage extends Usage {
+  message_id: string; // generated for title and summary usage
+  chat_id: string;
+  kind: 'reply' | 'title' | 'summary';
+  project_id?: string;
+  model: string;
+  created_at: string;
+}
+
+export interface UsageTotals extends Usage {
+  message_count: number;
+  cost_usd: number;
+}
+
+export interface DailyUsage extends UsageTotals {
+  day: string; // YYYY-MM-DD, UTC
+}
+
+export interface SearchHit {
+  chat_id: string;
+  chat_title: string;
+  project_id?: string;
+  message_id?: string; // absent for chat title matches
+  role?: 'user' | 'assistant';
+  snippet: string; // HTML-escaped, with matches wrapped in <mark>
+  created_at: string;
+  rank: number;
+}
+
+export interface SearchFilters {
+  projectId?: string;
+  role?: 'user' | 'assistant';
+  from?: string; // YYYY-MM-DD, inclusive
+  to?: string; // YYYY-MM-DD, inclusive
+  limit?: number;
+}
+
+export interface ModelPrice {
+  model: string;
+  input_per_mtok: number;
+  output_per_mtok: number;
+  cache_write_per_mtok: number; // five-minute TTL
+  cache_write_1h_per_mtok: number;
+  cache_read_per_mtok: number;
+}
+
+export type ThinkingBlock =
+  | { type: 'thinking'; thinking: string; signature: string }
+  | { type: 'redacted_thinking'; data: string };
+
+export interface Usage {
+  input_tokens: number;
+  output_tokens: number;
+  cache_creation_input_tokens: number; // cache misses written to the cache
+  cache_creation_1h_input_tokens: number; // the part written with the one-hour TTL
+  cache_read_input_tokens: number; // cache hits
+}
+
+// Markdown and HTML cover the active branch; JSON keeps every branch and attachment
+export type ExportFormat = 'markdown' | 'json' | 'html';
+
+export interface BackupInfo {
+  path: string;
+  file_name: string;
+  size_bytes: number;
   created_at: string;
+  scheduled: boolean; // scheduled backups are rotated; manual ones are kept
 }
 
-export interface StreamChunk {
-  delta: string;
-  done: boolean;
+export interface ImportSummary {
+  source: 'claude_ai' | 'chatgpt';
+  project_id: string;
+  chats_created: number;
+  chats_updated: number; // chats imported before that gained messages
+  messages_imported: number;
 }
+
+export type GenerationEvent =
+  | { type: 'start'; chat_id: string; message_id: string; model: string }
+  | { type: 'text_delta'; delta: string }
+  | { type: 'thinking_delta'; delta: string; signature?: string }
+  | ({ type: 'usage' } & Usage)
+  | { type: 'retry'; attempt: number; max_retries: number; delay_ms: number; reason: string }
+  | { type: 'tool_use'; id: string; name: string; input: unknown }
+  | { type: 'tool_result'; tool_use_id: string; content: string; is_error: boolean }
+  | { type: 'compacted'; omitted_message_ids: string[]; summarized: boolean }
+  | { type: 'stop'; stop_reason?: string }
+  | { type: 'error'; error: AppError };
+
+export interface ApiErrorDetails {
+  message: string;
+  error_type?: string; // Anthropic error type, e.g. 'rate_limit_error'
+  status?: number;
+  request_id?: string;
+  retry_after_ms?: number;
+}
+
+export type AppError =
+  | ({ code: 'rate_limited' | 'overloaded' | 'context_too_long' | 'authentication' | 'invalid_request' | 'api' } & ApiErrorDetails)
+  | {
+      code: 'missing_api_key' | 'configuration' | 'network' | 'database' | 'not_found' | 'serialization' | 'busy' | 'invalid_input' | 'tool' | 'internal';
+      message: string;
+    };
@@ -1,3 +1,5 @@
+import type { AppError } from './types';
+
 export function generateChatTitle(firstMessage: string): string {
   // Take first 50 characters of the message as title
   const cleaned = firstMessage.trim().replace(/\n/g, ' ');
@@ -6,3 +8,12 @@ export function generateChatTitle(firstMessage: string): string {
   }
   return cleaned.substring(0, 47) + '...';
 }
+
+export function isAppError(error: unknown): error is AppError {
+  return typeof error === 'object' && error !== null && 'code' in error && 'message' in error;
+}
+
+// Commands reject with a tagged AppError object; anything else is stringified
+export function errorMessage(error: unknown): string {
+  return isAppError(error) ? error.message : String(error);
+}
@@ -1,6 +1,7 @@
 import { create } from 'zustand';
-import type { Chat, Message, MessageImage, MessageDocument } from '../lib/types';
+import type { Chat, GenerationEvent, Message, MessageImage, MessageDocument } from '../lib/types';
 import * as api from '../lib/tauri';
+import { errorMessage } from '../lib/utils';
 
 interface ChatsState {
   chats: Chat[];
@@ -10,14 +11,24 @@ interface ChatsState {
   isSending: boolean;
   isThinking: boolean;
   streamingContent: string;
+  streamingThinking: string;
+  streamingMessageId: string | null;
+  retryAttempt: { attempt: number; maxRetries: number } | null;
+  runningTool: string | null;
+  // Messages the last reply was generated without, to fit the context window
+  omittedMessageIds: string[];
+  historySummarized: boolean;
   error: string | null;
 
   loadChats: (projectId?: string) => Promise<void>;
   selectChat: (chat: Chat | null) => Promise<void>;
   createChat: (projectId?: string) => Promise<Chat>;
   deleteChat: (chatId: string) => Promise<void>;
-  sendMessage: (content: string, projectId?: string, model?: string, images?: MessageImage[], extendedThinking?: boolean, documents?: MessageDocument[]) => Promise<void>;
+  sendMessage: (content: string, projectId?: string, model?: string, images?: MessageImage[], extendedThinking?: boolean, documents?: MessageDocument[], filePaths?: string[]) => Promise<void>;
+  cancelGeneration: () => Promise<void>;
+  handleGenerationEvent: (chatId: string, event: GenerationEvent) => void;
   appendStreamDelta: (delta: string) => void;
+  appendThinkingDelta: (delta: string) => void;
   finalizeStreamedMessage: () => void;
   clearMessages: () => void;
 }
@@ -30,6 +41,12 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
   isSending: false,
   isThinking: false,
   streamingContent: '',
+  streamingThinking: '',
+  streamingMessageId: null,
+  retryAttempt: null,
+  runningTool: null,
+  omittedMessageIds: [],
+  historySummarized: false,
   error: null,
 
   loadChats: async (projectId) => {
@@ -38,18 +55,18 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
       const chats = await api.listChats(projectId);
       set({ chats, isLoading: false });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: errorMessage(error), isLoading: false });
     }
   },
 
   selectChat: async (chat) => {
-    set({ currentChat: chat, isLoading: true, error: null, messages: [], streamingContent: '' });
+    set({ currentChat: chat, isLoading: true, error: null, messages: [], streamingContent: '', streamingThinking: '', streamingMessageId: null, omittedMessageIds: [], historySummarized: false });
     if (chat) {
       try {
         const messages = await api.listMessages(chat.id);
         set({ messages, isLoading: false });
       } catch (error) {
-        set({ error: String(error), isLoading: false });
+        set({ error: errorMessage(error), isLoading: false });
       }
     } else {
       set({ isLoading: false });
@@ -69,7 +86,7 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
       }));
       return chat;
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: errorMessage(error), isLoading: false });
       throw error;
     }
   },
@@ -85,12 +102,12 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: errorMessage(error), isLoading: false });
       throw error;
     }
   },
 
-  sendMessage: async (content, projectId, model, images, extendedThinking, documents) => {
+  sendMessage: async (content, projectId, model, images, extendedThinking, documents, filePaths) => {
     const { currentChat } = get();
     if (!currentChat) return;
 
@@ -100,8 +117,13 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
       chat_id: currentChat.id,
       role: 'user',
       content,
-      images,
-      documents,
+      // Shown from the data in hand until the stored message replaces it
+      attachments: [
+        ...(images || []).map((image) => ({ sha256: '', kind: 'image' as const, media_type: image.media_type, size_bytes: 0, data: image.data })),
+        ...(documents || []).map((doc) => ({ sha256: '', kind: 'document' as const, media_type: doc.media_type, name: doc.name, size_bytes: 0, data: doc.data })),
+        // Not read yet, so listed by name
+        ...(filePaths || []).map((path) => ({ sha256: '', kind: 'document' as const, media_type: '', name: path.split(/[\\/]/).pop(), size_bytes: 0 })),
+      ],
       model,
       extended_thinking: extendedThinking,
       created_at: new Date().toISOString(),
@@ -112,32 +134,97 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
       isSending: true,
       isThinking: extendedThinking || false,
       streamingContent: '',
+      streamingThinking: '',
+      omittedMessageIds: [],
+      historySummarized: false,
       error: null,
     }));
 
     try {
-      await api.sendMessage(currentChat.id, content, projectId, model, images, extendedThinking, documents);
+      const chatId = currentChat.id;
+      await api.sendMessage((event) => get().handleGenerationEvent(chatId, event), chatId, content, projectId, model, images, extendedThinking, documents, filePaths);
       // Title will be auto-generated in finalizeStreamedMessage after assistant responds
+      // A generation cancelled before any text arrives never finalizes
+      set({ isSending: false, isThinking: false, retryAttempt: null, runningTool: null });
     } catch (error) {
-      set({ error: String(error), isSending: false, isThinking: false });
+      set({ error: errorMessage(error), isSending: false, isThinking: false, retryAttempt: null, runningTool: null });
+    }
+  },
+
+  cancelGeneration: async () => {
+    const { currentChat } = get();
+    if (!currentChat) return;
+
+    try {
+      await api.cancelGeneration(currentChat.id);
+    } catch (error) {
+      set({ error: errorMessage(error) });
+    }
+  },
+
+  handleGenerationEvent: (chatId, event) => {
+    // Ignore output from a chat that is no longer on screen
+    if (get().currentChat?.id !== chatId) return;
+
+    switch (event.type) {
+      case 'start':
+        set({ streamingMessageId: event.message_id });
+        break;
+      case 'retry':
+        set({ retryAttempt: { attempt: event.attempt, maxRetries: event.max_retries } });
+        break;
+      case 'text_delta':
+        get().appendStreamDelta(event.delta);
+        break;
+      case 'thinking_delta':
+        if (event.delta) {
+          get().appendThinkingDelta(event.delta);
+        }
+        break;
+      case 'compacted':
+        set({ omittedMessageIds: event.omitted_message_ids, historySummarized: event.summarized });
+        break;
+      case 'tool_use':
+        set({ runningTool: event.name });
+        break;
+      case 'tool_result':
+        set({ runningTool: null });
+        break;
+      case 'stop':
+        get().finalizeStreamedMessage();
+        break;
+      case 'error':
+        set({ error: event.error.message });
+        break;
     }
   },
 
   appendStreamDelta: (delta) => {
     set((state) => ({
       streamingContent: state.streamingContent + delta,
+      retryAttempt: null,
       isThinking: false, // Once we start getting content, thinking is done
     }));
   },
 
+  appendThinkingDelta: (delta) => {
+    set((state) => ({
+      streamingThinking: state.streamingThinking + delta,
+    }));
+  },
+
   finalizeStreamedMessage: async () => {
-    const { streamingContent, currentChat, messages } = get();
+    const { streamingContent, streamingThinking, streamingMessageId, currentChat, messages } = get();
     if (streamingContent && currentChat) {
       const assistantMessage: Message = {
-        id: `msg-${Date.now()}`,
+        id: streamingMessageId ?? `msg-${Date.now()}`,
         chat_id: currentChat.id,
         role: 'assistant',
         content: streamingContent,
+        attachments: [],
+        thinking: streamingThinking
+          ? JSON.stringify([{ type: 'thinking', thinking: streamingThinking, signature: '' }])
+          : undefined,
         created_at: new Date().toISOString(),
       };
 
@@ -146,6 +233,8 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
       set({
         messages: updatedMessages,
         streamingContent: '',
+        streamingThinking: '',
+        streamingMessageId: null,
         isSending: false,
         isThinking: false,
       });
@@ -156,7 +245,7 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
         if (firstUserMessage) {
           try {
             // Use AI to generate title based on conversation
-            const title = await api.generateTitle(firstUserMessage.content, streamingContent);
+            const title = await api.generateTitle(currentChat.id, firstUserMessage.content, streamingContent);
             await api.updateChatTitle(currentChat.id, title);
 
             // Update local state
@@ -175,6 +264,6 @@ export const useChatsStore = create<ChatsState>((set, get) => ({
   },
 
   clearMessages: () => {
-    set({ messages: [], streamingContent: '' });
+    set({ messages: [], streamingContent: '', streamingThinking: '' });
   },
 }));
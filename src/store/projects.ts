@@ -1,6 +1,7 @@
 import { create } from 'zustand';
 import type { Project } from '../lib/types';
 import * as api from '../lib/tauri';
+import { errorMessage } from '../lib/utils';
 
 interface ProjectsState {
   projects: Project[];
@@ -26,7 +27,7 @@ export const useProjectsStore = create<ProjectsState>((set) => ({
       const projects = await api.listProjects();
       set({ projects, isLoading: false });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: errorMessage(error), isLoading: false });
     }
   },
 
@@ -44,7 +45,7 @@ export const useProjectsStore = create<ProjectsState>((set) => ({
       }));
       return project;
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: errorMessage(error), isLoading: false });
       throw error;
     }
   },
@@ -59,7 +60,7 @@ export const useProjectsStore = create<ProjectsState>((set) => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: errorMessage(error), isLoading: false });
       throw error;
     }
   },